@@ -1,7 +1,7 @@
 use bumpalo::Bump;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rand::SeedableRng;
-use eclipse_sim::{simulate_battle_bump, BattleResult, Fleet, Ship, ShipType};
+use eclipse_sim::{simulate_battle_bump, Fleet, Ship, ShipType, Targeting, Weapon};
 
 fn benchmark_my_function(c: &mut Criterion) {
     let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(3);
@@ -11,8 +11,7 @@ fn benchmark_my_function(c: &mut Criterion) {
         initiative: 0,
         shield: 1,
         computer: 1,
-        weapon_1_dmg: 2,
-        weapon_2_dmg: 0,
+        weapons: vec![Weapon::cannon(2)],
         ship_type: ShipType::Interceptor,
     };
     let ship_proto_def = Ship {
@@ -20,14 +19,13 @@ fn benchmark_my_function(c: &mut Criterion) {
         initiative: 0,
         shield: 1,
         computer: 1,
-        weapon_1_dmg: 2,
-        weapon_2_dmg: 0,
+        weapons: vec![Weapon::cannon(2)],
         ship_type: ShipType::Interceptor,
     };
 
-    let mut bump = Bump::new();
-    let attacker_fleet = Fleet::new(vec!(ship_proto.clone(); 20), &bump);
-    let defender_fleet = Fleet::new(vec!(ship_proto_def.clone(); 20), &bump);
+    let bump = Bump::new();
+    let attacker_fleet = Fleet::new(vec!(ship_proto.clone(); 20), &bump).unwrap();
+    let defender_fleet = Fleet::new(vec!(ship_proto_def.clone(); 20), &bump).unwrap();
     
     c.bench_function("simulate_battle", |b| {
         let mut bump = Bump::new();
@@ -43,5 +41,30 @@ fn benchmark_my_function(c: &mut Criterion) {
     });
 }
 
-criterion_group!(benches, benchmark_my_function);
+/// Optimal targeting searches the hit assignment, which must stay fast enough for about 20 ships per fleet
+fn benchmark_optimal_targeting(c: &mut Criterion) {
+    let mut rng = rand_chacha::ChaCha8Rng::seed_from_u64(3);
+    let interceptor = Ship::new(1, 2, 0, 1, vec![Weapon::cannon(1), Weapon::cannon(2)], ShipType::Interceptor);
+    let cruiser = Ship::new(2, 1, 1, 1, vec![Weapon::cannon(2), Weapon::cannon(4).with_split_damage()], ShipType::Cruiser);
+    let ships = [vec![interceptor; 12], vec![cruiser; 8]].concat();
+
+    let bump = Bump::new();
+    let attacker_fleet = Fleet::new(ships.clone(), &bump).unwrap().with_targeting(Targeting::Optimal);
+    let defender_fleet = Fleet::new(ships, &bump).unwrap();
+
+    c.bench_function("simulate_battle_optimal_targeting", |b| {
+        let mut bump = Bump::new();
+        b.iter(|| {
+            simulate_battle_bump(
+                black_box(&mut attacker_fleet.clone()),
+                black_box(&mut defender_fleet.clone()),
+                black_box(&mut rng),
+                &bump
+            );
+            bump.reset()
+        })
+    });
+}
+
+criterion_group!(benches, benchmark_my_function, benchmark_optimal_targeting);
 criterion_main!(benches);
\ No newline at end of file
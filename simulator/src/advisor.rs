@@ -0,0 +1,137 @@
+use bumpalo::Bump;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use crate::blueprint::{Blueprint, Part};
+use crate::simulator::{simulate_battle_rounds, Fleet, Ship, ShipType, WasmFleet};
+use crate::statistics::BattleStatistics;
+
+/// A single part placed in a blueprint of the player fleet
+#[derive(Debug, Clone, PartialEq)]
+pub struct Upgrade {
+    pub ship_type: ShipType,
+    pub slot: usize,
+    pub part: Part,
+    /// The part that was in the slot before, None if the slot was empty
+    pub replaced: Option<Part>,
+    /// The upgraded blueprint
+    pub blueprint: Blueprint,
+    pub win_probability: f64,
+    /// The change of the win probability compared to the current blueprints
+    pub improvement: f64,
+}
+
+/// Tries every legal one-part upgrade of the blueprints in the player fleet and ranks them
+/// by the change in win probability against the opponent, best first.
+/// The player fleet has the given number of ships of each blueprint, and all upgrades of a blueprint
+/// apply to all of its ships. Only one empty slot per blueprint is tried, since all empty slots are equal.
+///
+/// Every candidate is evaluated with the same dice: battle i always uses stream i of the seed.
+/// These common random numbers make the differences between candidates much more stable
+/// than the win probabilities themselves
+pub fn rank_upgrades(
+    player: &[(Blueprint, usize)],
+    opponent: &WasmFleet,
+    player_attacks: bool,
+    battles: usize,
+    seed: u64,
+) -> Vec<Upgrade> {
+    let current = win_probability(player, opponent, player_attacks, battles, seed);
+    let mut upgrades = Vec::new();
+    for (index, (blueprint, count)) in player.iter().enumerate() {
+        if *count == 0 {
+            continue;
+        }
+        let slots = blueprint.parts().len() + usize::from(blueprint.free_slots() > 0);
+        for slot in 0..slots {
+            let replaced = blueprint.parts().get(slot).copied();
+            for part in Part::ALL.into_iter().filter(|part| Some(*part) != replaced) {
+                let Ok(upgraded) = blueprint.with_part(slot, part) else {
+                    continue;
+                };
+                let mut candidate = player.to_vec();
+                candidate[index].0 = upgraded.clone();
+                let probability = win_probability(&candidate, opponent, player_attacks, battles, seed);
+                upgrades.push(Upgrade {
+                    ship_type: blueprint.ship_type(),
+                    slot,
+                    part,
+                    replaced,
+                    blueprint: upgraded,
+                    win_probability: probability,
+                    improvement: probability - current,
+                });
+            }
+        }
+    }
+    upgrades.sort_by(|a, b| b.improvement.total_cmp(&a.improvement));
+    upgrades
+}
+
+/// The win probability of the player fleet, simulated with one ChaCha8 stream per battle
+pub(crate) fn win_probability(
+    player: &[(Blueprint, usize)],
+    opponent: &WasmFleet,
+    player_attacks: bool,
+    battles: usize,
+    seed: u64,
+) -> f64 {
+    let bump = Bump::new();
+    let ships = player
+        .iter()
+        .flat_map(|(blueprint, count)| std::iter::repeat_n(blueprint.to_ship(), *count))
+        .collect::<Vec<Ship>>();
+    // Blueprints always compile to valid stats
+    let mut fleet = Fleet::from_valid_ships(ships, &bump);
+    if let Some((blueprint, _)) = player.first() {
+        fleet = fleet.with_modifiers(blueprint.species().combat_modifiers());
+    }
+    let opponent = opponent.clone().into_fleet(&bump);
+    let (attacker, defender) = match player_attacks {
+        true => (fleet, opponent),
+        false => (opponent, fleet),
+    };
+
+    let mut statistics = BattleStatistics::default();
+    for battle in 0..battles {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        rng.set_stream(battle as u64);
+        let mut attacker = attacker.clone();
+        let mut defender = defender.clone();
+        let (result, rounds) = simulate_battle_rounds(&mut attacker, &mut defender, &mut rng, &bump);
+        statistics.record(result, rounds, &attacker, &defender);
+    }
+    match player_attacks {
+        true => statistics.attacker_win_rate(),
+        false => statistics.defender_win_rate(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::advisor::rank_upgrades;
+    use crate::blueprint::{Blueprint, Part};
+    use crate::simulator::{ShipType, WasmFleet};
+
+    #[test]
+    pub fn test_rank_upgrades() {
+        let interceptor = Blueprint::default_for(ShipType::Interceptor).unwrap();
+        let player = [(interceptor.clone(), 2)];
+        let ancients = WasmFleet::ancients(1, false);
+
+        let upgrades = rank_upgrades(&player, &ancients, true, 400, 0);
+        assert!(upgrades.windows(2).all(|pair| pair[0].improvement >= pair[1].improvement));
+        assert!(upgrades.iter().all(|upgrade| upgrade.blueprint == interceptor.with_part(upgrade.slot, upgrade.part).unwrap()));
+        // Interceptors need a drive, and a plasma cannon can not replace the only source
+        assert!(upgrades.iter().all(|upgrade| upgrade.blueprint.parts().iter().any(|part| part.stats().drive)));
+        assert!(!upgrades.iter().any(|upgrade| upgrade.slot == 1 && upgrade.part == Part::PlasmaCannon));
+
+        let best = &upgrades[0];
+        assert!(best.improvement > 0.05, "{:?}", best);
+        // With the same dice, a part that does not change the ship does not change the outcome
+        let useless = upgrades.iter().find(|upgrade| upgrade.slot == 3 && upgrade.part == Part::NuclearSource).unwrap();
+        assert_eq!(useless.improvement, 0.);
+
+        // The ranking is reproducible
+        assert_eq!(rank_upgrades(&player, &ancients, true, 400, 0), upgrades);
+    }
+}
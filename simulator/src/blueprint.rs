@@ -0,0 +1,318 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::error::BlueprintError;
+use crate::simulator::{Ship, ShipType, Weapon};
+use crate::species::Species;
+
+/// The ship parts of the Second Dawn tech tiles
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Part {
+    IonCannon,
+    PlasmaCannon,
+    SolitonCannon,
+    AntimatterCannon,
+    RiftCannon,
+    FluxMissile,
+    PlasmaMissile,
+    ElectronComputer,
+    PositronComputer,
+    GluonComputer,
+    GaussShield,
+    PhaseShield,
+    /// A shield that also produces energy
+    AbsorptionShield,
+    Hull,
+    ImprovedHull,
+    ConifoldField,
+    NuclearDrive,
+    FusionDrive,
+    TachyonDrive,
+    NuclearSource,
+    FusionSource,
+    TachyonSource,
+    ZeroPointSource,
+}
+
+/// The stats a part adds to a ship. Energy is positive for sources and negative for parts that use energy
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PartStats {
+    pub hull: i32,
+    pub initiative: i32,
+    pub shield: i32,
+    pub computer: i32,
+    pub energy: i32,
+    pub weapon: Option<Weapon>,
+    pub drive: bool,
+}
+
+impl Part {
+    /// Every part, in the order of the tech tracks
+    pub const ALL: [Part; 23] = [
+        Part::IonCannon,
+        Part::PlasmaCannon,
+        Part::SolitonCannon,
+        Part::AntimatterCannon,
+        Part::RiftCannon,
+        Part::FluxMissile,
+        Part::PlasmaMissile,
+        Part::ElectronComputer,
+        Part::PositronComputer,
+        Part::GluonComputer,
+        Part::GaussShield,
+        Part::PhaseShield,
+        Part::AbsorptionShield,
+        Part::Hull,
+        Part::ImprovedHull,
+        Part::ConifoldField,
+        Part::NuclearDrive,
+        Part::FusionDrive,
+        Part::TachyonDrive,
+        Part::NuclearSource,
+        Part::FusionSource,
+        Part::TachyonSource,
+        Part::ZeroPointSource,
+    ];
+
+    pub fn stats(&self) -> PartStats {
+        let none = PartStats::default();
+        let weapon = |weapon: Weapon, energy: i32| PartStats { weapon: Some(weapon), energy, ..none };
+        let drive = |initiative: i32| PartStats { initiative, energy: -initiative, drive: true, ..none };
+        match self {
+            Part::IonCannon => weapon(Weapon::cannon(1), -1),
+            Part::PlasmaCannon => weapon(Weapon::cannon(2), -2),
+            Part::SolitonCannon => weapon(Weapon::cannon(3), -3),
+            Part::AntimatterCannon => weapon(Weapon::cannon(4), -4),
+            Part::RiftCannon => weapon(Weapon::rift_cannon(), -2),
+            Part::FluxMissile => PartStats { initiative: 1, ..weapon(Weapon::missile(1), 0) },
+            Part::PlasmaMissile => weapon(Weapon::missile(2), -1),
+            Part::ElectronComputer => PartStats { computer: 1, ..none },
+            Part::PositronComputer => PartStats { computer: 2, initiative: 1, energy: -1, ..none },
+            Part::GluonComputer => PartStats { computer: 3, initiative: 2, energy: -2, ..none },
+            Part::GaussShield => PartStats { shield: 1, ..none },
+            Part::PhaseShield => PartStats { shield: 2, energy: -1, ..none },
+            Part::AbsorptionShield => PartStats { shield: 1, energy: 4, ..none },
+            Part::Hull => PartStats { hull: 1, ..none },
+            Part::ImprovedHull => PartStats { hull: 2, ..none },
+            Part::ConifoldField => PartStats { hull: 3, energy: -2, ..none },
+            Part::NuclearDrive => drive(1),
+            Part::FusionDrive => drive(2),
+            Part::TachyonDrive => drive(3),
+            Part::NuclearSource => PartStats { energy: 3, ..none },
+            Part::FusionSource => PartStats { energy: 6, ..none },
+            Part::TachyonSource => PartStats { energy: 9, ..none },
+            Part::ZeroPointSource => PartStats { energy: 12, ..none },
+        }
+    }
+}
+
+impl ShipType {
+    /// The number of part slots of the standard blueprint, NPC ships have no blueprint
+    pub fn blueprint_slots(&self) -> Option<usize> {
+        match self {
+            ShipType::Interceptor => Some(4),
+            ShipType::Cruiser => Some(6),
+            ShipType::Dreadnought => Some(8),
+            ShipType::Starbase => Some(5),
+            ShipType::Ancient | ShipType::Guardian | ShipType::Gcds => None,
+        }
+    }
+
+    /// The initiative printed on the standard blueprint
+    pub fn base_initiative(&self) -> i32 {
+        match self {
+            ShipType::Interceptor => 2,
+            ShipType::Cruiser => 1,
+            ShipType::Starbase => 4,
+            _ => 0,
+        }
+    }
+
+    /// The energy printed on the standard blueprint
+    pub fn base_energy(&self) -> i32 {
+        match self {
+            ShipType::Starbase => 3,
+            _ => 0,
+        }
+    }
+}
+
+/// A ship type with the parts placed in its slots. Empty slots are not stored.
+/// The number of slots and the printed initiative and energy depend on the species
+#[wasm_bindgen]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "UncheckedBlueprint")]
+pub struct Blueprint {
+    #[serde(default)]
+    species: Species,
+    ship_type: ShipType,
+    parts: Vec<Part>,
+}
+
+/// A blueprint as it is read, before the parts are checked
+#[derive(Deserialize)]
+struct UncheckedBlueprint {
+    #[serde(default)]
+    species: Species,
+    ship_type: ShipType,
+    parts: Vec<Part>,
+}
+
+impl TryFrom<UncheckedBlueprint> for Blueprint {
+    type Error = BlueprintError;
+
+    fn try_from(blueprint: UncheckedBlueprint) -> Result<Blueprint, BlueprintError> {
+        Blueprint::for_species(blueprint.species, blueprint.ship_type, blueprint.parts)
+    }
+}
+
+impl Blueprint {
+    /// A blueprint on the standard player board.
+    /// Fails if the parts do not fit the slots, use more energy than is produced,
+    /// or the ship can not move, see [`BlueprintError`]
+    pub fn new(ship_type: ShipType, parts: Vec<Part>) -> Result<Blueprint, BlueprintError> {
+        Blueprint::for_species(Species::Terran, ship_type, parts)
+    }
+
+    /// A blueprint on the player board of the species, see [`Blueprint::new`]
+    pub fn for_species(species: Species, ship_type: ShipType, parts: Vec<Part>) -> Result<Blueprint, BlueprintError> {
+        let slots = species.blueprint_slots(ship_type).ok_or(BlueprintError::NoBlueprint(ship_type))?;
+        if parts.len() > slots {
+            return Err(BlueprintError::TooManyParts { ship_type, parts: parts.len(), slots });
+        }
+        let has_drive = parts.iter().any(|part| part.stats().drive);
+        match ship_type {
+            ShipType::Starbase if has_drive => return Err(BlueprintError::DriveOnStarbase),
+            ShipType::Starbase => {}
+            _ if !has_drive => return Err(BlueprintError::MissingDrive(ship_type)),
+            _ => {}
+        }
+        let blueprint = Blueprint { species, ship_type, parts };
+        if blueprint.energy_balance() < 0 {
+            return Err(BlueprintError::NotEnoughEnergy {
+                production: blueprint.energy_production(),
+                usage: blueprint.energy_production() - blueprint.energy_balance(),
+            });
+        }
+        Ok(blueprint)
+    }
+
+    /// The blueprint printed on the standard player board, shared by most species.
+    /// See [`Species::default_blueprint`] for the others
+    pub fn default_for(ship_type: ShipType) -> Result<Blueprint, BlueprintError> {
+        Species::Terran.default_blueprint(ship_type)
+    }
+
+    pub fn species(&self) -> Species {
+        self.species
+    }
+
+    pub fn parts(&self) -> &[Part] {
+        &self.parts
+    }
+
+    /// Replaces the part in the given slot, or fills an empty slot if the index is past the placed parts
+    pub fn with_part(&self, slot: usize, part: Part) -> Result<Blueprint, BlueprintError> {
+        let mut parts = self.parts.clone();
+        match parts.get_mut(slot) {
+            Some(placed) => *placed = part,
+            None => parts.push(part),
+        }
+        Blueprint::for_species(self.species, self.ship_type, parts)
+    }
+
+    fn energy_production(&self) -> i32 {
+        self.species.base_energy(self.ship_type) + self.parts.iter().map(|part| part.stats().energy.max(0)).sum::<i32>()
+    }
+}
+
+#[wasm_bindgen]
+impl Blueprint {
+    pub fn ship_type(&self) -> ShipType {
+        self.ship_type
+    }
+
+    /// The number of empty slots
+    pub fn free_slots(&self) -> usize {
+        self.species.blueprint_slots(self.ship_type).unwrap_or(0).saturating_sub(self.parts.len())
+    }
+
+    /// The energy that is produced but not used by the parts
+    pub fn energy_balance(&self) -> i32 {
+        self.species.base_energy(self.ship_type) + self.parts.iter().map(|part| part.stats().energy).sum::<i32>()
+    }
+
+    /// The combat stats of a ship built from this blueprint
+    pub fn to_ship(&self) -> Ship {
+        let stats = self.parts.iter().map(Part::stats);
+        Ship::new(
+            stats.clone().map(|stats| stats.hull).sum(),
+            self.species.base_initiative(self.ship_type) + stats.clone().map(|stats| stats.initiative).sum::<i32>(),
+            stats.clone().map(|stats| stats.shield).sum(),
+            stats.clone().map(|stats| stats.computer).sum(),
+            stats.filter_map(|stats| stats.weapon).collect(),
+            self.ship_type,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blueprint::{Blueprint, Part};
+    use crate::error::BlueprintError;
+    use crate::simulator::{ShipType, WeaponKind};
+
+    #[test]
+    pub fn test_default_blueprints() {
+        let cruiser = Blueprint::default_for(ShipType::Cruiser).unwrap();
+        assert_eq!(cruiser.free_slots(), 1);
+        assert_eq!(cruiser.energy_balance(), 1);
+        let ship = cruiser.to_ship();
+        assert_eq!((ship.hull, ship.initiative, ship.shield, ship.computer), (1, 2, 0, 1));
+        assert_eq!(ship.weapons.len(), 1);
+        assert_eq!((ship.weapons[0].kind, ship.weapons[0].damage, ship.weapons[0].dice), (WeaponKind::Cannon, 1, 1));
+
+        let dreadnought = Blueprint::default_for(ShipType::Dreadnought).unwrap().to_ship();
+        assert_eq!((dreadnought.hull, dreadnought.initiative, dreadnought.computer, dreadnought.weapons.len()), (2, 1, 1, 2));
+        let interceptor = Blueprint::default_for(ShipType::Interceptor).unwrap().to_ship();
+        assert_eq!((interceptor.hull, interceptor.initiative), (0, 3));
+        let starbase = Blueprint::default_for(ShipType::Starbase).unwrap().to_ship();
+        assert_eq!((starbase.hull, starbase.initiative, starbase.computer), (2, 4, 1));
+        assert_eq!(Blueprint::default_for(ShipType::Ancient), Err(BlueprintError::NoBlueprint(ShipType::Ancient)));
+    }
+
+    #[test]
+    pub fn test_blueprint_limits() {
+        let interceptor = Blueprint::default_for(ShipType::Interceptor).unwrap();
+        // Two plasma cannons use four energy, the nuclear source and drive leave only two
+        let upgraded = interceptor.with_part(0, Part::PlasmaCannon).unwrap();
+        assert_eq!(upgraded.energy_balance(), 0);
+        assert_eq!(
+            upgraded.with_part(3, Part::PlasmaCannon),
+            Err(BlueprintError::NotEnoughEnergy { production: 3, usage: 5 })
+        );
+        let full = upgraded.with_part(3, Part::Hull).unwrap();
+        assert_eq!(full.free_slots(), 0);
+        assert_eq!(
+            full.with_part(4, Part::Hull),
+            Err(BlueprintError::TooManyParts { ship_type: ShipType::Interceptor, parts: 5, slots: 4 })
+        );
+        assert_eq!(interceptor.with_part(2, Part::Hull), Err(BlueprintError::MissingDrive(ShipType::Interceptor)));
+        let starbase = Blueprint::default_for(ShipType::Starbase).unwrap();
+        assert_eq!(starbase.with_part(4, Part::NuclearDrive), Err(BlueprintError::DriveOnStarbase));
+
+        // The absorption shield powers a plasma cannon and a fusion drive without any source
+        let shielded = Blueprint::new(ShipType::Cruiser, vec![Part::AbsorptionShield, Part::PlasmaCannon, Part::FusionDrive]).unwrap();
+        assert_eq!(shielded.energy_balance(), 0);
+        let ship = shielded.to_ship();
+        assert_eq!((ship.hull, ship.initiative, ship.shield), (0, 3, 1));
+
+        // Blueprints read from JSON are checked the same way
+        let json = serde_json::to_string(&shielded).unwrap();
+        assert_eq!(serde_json::from_str::<Blueprint>(&json).unwrap(), shielded);
+        let overloaded = r#"{"ship_type": "Interceptor", "parts": ["IonCannon", "IonCannon", "IonCannon", "IonCannon", "IonCannon", "IonCannon"]}"#;
+        let error = serde_json::from_str::<Blueprint>(overloaded).unwrap_err();
+        assert!(error.to_string().starts_with("6 parts do not fit the 4 slots of a Interceptor"), "{}", error);
+        assert!(serde_json::from_str::<Blueprint>(r#"{"ship_type": "Ancient", "parts": []}"#).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use crate::advisor::win_probability;
+use crate::blueprint::Blueprint;
+use crate::simulator::{ShipType, WasmFleet};
+
+impl ShipType {
+    /// The number of ship miniatures of a player
+    pub fn max_ships(&self) -> Option<u32> {
+        match self {
+            ShipType::Interceptor => Some(8),
+            ShipType::Cruiser => Some(4),
+            ShipType::Dreadnought => Some(2),
+            ShipType::Starbase => Some(4),
+            ShipType::Ancient | ShipType::Guardian | ShipType::Gcds => None,
+        }
+    }
+}
+
+/// A fleet built from the player blueprints
+#[derive(Debug, Clone, PartialEq)]
+pub struct Composition {
+    /// The number of ships of each blueprint, in the order of the blueprints
+    pub ships: Vec<u32>,
+    pub cost: u32,
+    pub win_probability: f64,
+}
+
+/// Finds the fleets that can be built from the blueprints with the material budget
+/// and ranks them by the win probability against the opponent, best first.
+/// On equal win probability the cheaper fleet is ranked first.
+///
+/// Only fleets to which no further ship can be added are evaluated, since an additional ship never hurts.
+/// Starbases can not move, so they are left out when the player attacks.
+/// All fleets are evaluated with the same dice, see [`rank_upgrades`](crate::advisor::rank_upgrades)
+pub fn rank_compositions(
+    blueprints: &[Blueprint],
+    budget: u32,
+    opponent: &WasmFleet,
+    player_attacks: bool,
+    battles: usize,
+    seed: u64,
+) -> Vec<Composition> {
+    let costs = blueprints
+        .iter()
+        .map(|blueprint| match blueprint.ship_type() {
+            ShipType::Starbase if player_attacks => None,
+            ship_type => ship_type.build_cost(),
+        })
+        .collect::<Vec<Option<u32>>>();
+    let mut compositions = Vec::new();
+    enumerate(blueprints, &costs, budget, &mut vec![0; blueprints.len()], 0, &mut compositions);
+
+    for composition in compositions.iter_mut() {
+        let player = blueprints
+            .iter()
+            .cloned()
+            .zip(composition.ships.iter().map(|count| *count as usize))
+            .collect::<Vec<(Blueprint, usize)>>();
+        composition.win_probability = win_probability(&player, opponent, player_attacks, battles, seed);
+    }
+    compositions.sort_by(|a, b| b.win_probability.total_cmp(&a.win_probability).then(a.cost.cmp(&b.cost)));
+    compositions
+}
+
+/// The number of ships of the type in the fleet, the miniatures are shared by all blueprints of a type
+fn built(blueprints: &[Blueprint], ships: &[u32], ship_type: ShipType) -> u32 {
+    ships
+        .iter()
+        .zip(blueprints)
+        .filter(|(_, blueprint)| blueprint.ship_type() == ship_type)
+        .map(|(count, _)| count)
+        .sum()
+}
+
+/// Tries every count for the blueprint at the index and the ones after it, with the remaining materials
+fn enumerate(
+    blueprints: &[Blueprint],
+    costs: &[Option<u32>],
+    remaining: u32,
+    ships: &mut Vec<u32>,
+    index: usize,
+    compositions: &mut Vec<Composition>,
+) {
+    let can_build = |ships: &[u32], i: usize, remaining: u32| match costs[i] {
+        Some(cost) => cost <= remaining && built(blueprints, ships, blueprints[i].ship_type()) < blueprints[i].ship_type().max_ships().unwrap_or(0),
+        None => false,
+    };
+    if index == blueprints.len() {
+        let full = !(0..blueprints.len()).any(|i| can_build(ships, i, remaining));
+        if full && ships.iter().any(|count| *count > 0) {
+            let cost = ships.iter().zip(costs).map(|(count, cost)| count * cost.unwrap_or(0)).sum();
+            compositions.push(Composition { ships: ships.clone(), cost, win_probability: 0. });
+        }
+        return;
+    }
+    let mut remaining = remaining;
+    let mut added = 0;
+    loop {
+        enumerate(blueprints, costs, remaining, ships, index + 1, compositions);
+        if !can_build(ships, index, remaining) {
+            break;
+        }
+        ships[index] += 1;
+        added += 1;
+        remaining -= costs[index].unwrap();
+    }
+    ships[index] -= added;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blueprint::Blueprint;
+    use crate::composition::rank_compositions;
+    use crate::simulator::{ShipType, WasmFleet};
+
+    #[test]
+    pub fn test_rank_compositions() {
+        let blueprints = [ShipType::Interceptor, ShipType::Cruiser, ShipType::Dreadnought, ShipType::Starbase]
+            .map(|ship_type| Blueprint::default_for(ship_type).unwrap());
+        let guardian = WasmFleet::guardian(false);
+
+        let compositions = rank_compositions(&blueprints, 12, &guardian, true, 300, 0);
+        assert!(!compositions.is_empty());
+        assert!(compositions.windows(2).all(|pair| pair[0].win_probability >= pair[1].win_probability));
+        for composition in compositions.iter() {
+            assert!(composition.cost <= 12 && composition.cost > 12 - 3, "{:?}", composition);
+            assert_eq!(composition.ships[3], 0);
+            assert!(composition.ships[2] <= 2);
+        }
+        // Four interceptors, or a dreadnought and an interceptor with one material to spare
+        assert!(compositions.iter().any(|composition| composition.ships == [4, 0, 0, 0]));
+        assert!(compositions.iter().any(|composition| composition.ships == [1, 0, 1, 0] && composition.cost == 11));
+
+        // Starbases can defend, and more materials never lower the best win probability
+        let defending = rank_compositions(&blueprints, 12, &guardian, false, 300, 0);
+        assert!(defending.iter().any(|composition| composition.ships[3] > 0));
+        let richer = rank_compositions(&blueprints, 20, &guardian, true, 300, 0);
+        assert!(richer[0].win_probability >= compositions[0].win_probability);
+    }
+}
@@ -0,0 +1,130 @@
+use rand::{Rng, RngCore};
+use crate::error::ScriptError;
+use crate::simulator::{simulate_battle, BattleResult, Fleet};
+
+/// A source of d6 results for the simulation. Every random number generator is one
+pub trait Dice {
+    /// Rolls a single die and returns its face, from 1 to 6
+    fn roll(&mut self) -> i32;
+
+    /// True once a die was requested that the source could not roll.
+    /// The battle ends without a new round as soon as this happens
+    fn ran_out(&self) -> bool {
+        false
+    }
+}
+
+impl<T: RngCore> Dice for T {
+    #[inline]
+    fn roll(&mut self) -> i32 {
+        self.random_range(1..=6)
+    }
+}
+
+/// Replays a fixed sequence of d6 results, e.g. dice recorded in a battle or rolled at the table.
+/// The dice are used in the order the simulation rolls them: by initiative, ship and weapon.
+/// Once the script is used up, further dice are ones, which never hit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptedDice {
+    faces: Vec<i32>,
+    next: usize,
+    ran_out: bool,
+}
+
+impl ScriptedDice {
+    /// Fails if a face is not between 1 and 6
+    pub fn new(faces: Vec<i32>) -> Result<ScriptedDice, ScriptError> {
+        if let Some((index, face)) = faces.iter().enumerate().find(|(_, face)| !(1..=6).contains(*face)) {
+            return Err(ScriptError::InvalidFace { index, face: *face });
+        }
+        Ok(ScriptedDice { faces, next: 0, ran_out: false })
+    }
+
+    /// The number of dice that were rolled from the script
+    pub fn rolled(&self) -> usize {
+        self.next
+    }
+
+    /// The dice that were not rolled yet
+    pub fn remaining(&self) -> &[i32] {
+        &self.faces[self.next..]
+    }
+}
+
+impl Dice for ScriptedDice {
+    fn roll(&mut self) -> i32 {
+        match self.faces.get(self.next) {
+            Some(face) => {
+                self.next += 1;
+                *face
+            }
+            None => {
+                self.ran_out = true;
+                1
+            }
+        }
+    }
+
+    fn ran_out(&self) -> bool {
+        self.ran_out
+    }
+}
+
+/// Simulates a battle with the dice of the script instead of random dice.
+/// Fails if the battle needs more dice than the script contains, the fleets are then left in the state
+/// before the first missing die. Unused dice at the end of the script are not an error, see [`ScriptedDice::remaining`]
+pub fn replay_battle(attacker: &mut Fleet, defender: &mut Fleet, dice: &mut ScriptedDice) -> Result<BattleResult, ScriptError> {
+    let result = simulate_battle(attacker, defender, dice);
+    match dice.ran_out() {
+        true => Err(ScriptError::OutOfDice { rolled: dice.rolled() }),
+        false => Ok(result),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::dice::{replay_battle, ScriptedDice};
+    use crate::error::ScriptError;
+    use crate::events::{record_battle, BattleEvent};
+    use crate::simulator::{BattleResult, Fleet, Ship, ShipType, Weapon};
+
+    #[test]
+    pub fn test_replay_battle() {
+        let bump = bumpalo::Bump::new();
+        let interceptor = |initiative: i32| Ship::new(0, initiative, 0, 0, vec![Weapon::cannon(1)], ShipType::Interceptor);
+        let attacker = Fleet::new(vec![interceptor(3)], &bump).unwrap();
+        let defender = Fleet::new(vec![interceptor(2)], &bump).unwrap();
+        let replay = |faces: Vec<i32>| {
+            let mut dice = ScriptedDice::new(faces).unwrap();
+            let result = replay_battle(&mut attacker.clone(), &mut defender.clone(), &mut dice);
+            (result, dice.remaining().len())
+        };
+
+        // The attacker shoots first
+        assert_eq!(replay(vec![6]), (Ok(BattleResult::AttackerWins), 0));
+        assert_eq!(replay(vec![5, 6, 3]), (Ok(BattleResult::DefenderWins), 1));
+        assert_eq!(replay(vec![2, 4, 1, 6]), (Ok(BattleResult::DefenderWins), 0));
+        assert_eq!(replay(vec![2, 4, 1]), (Err(ScriptError::OutOfDice { rolled: 3 }), 0));
+        assert_eq!(ScriptedDice::new(vec![3, 7]), Err(ScriptError::InvalidFace { index: 1, face: 7 }));
+
+        // The dice of a recorded battle replay the same battle
+        let cruiser = Ship::new(1, 2, 1, 1, vec![Weapon::cannon(1), Weapon::rift_cannon(), Weapon::missile(1)], ShipType::Cruiser);
+        let attacker = Fleet::new(vec![cruiser.clone(), cruiser], &bump).unwrap();
+        let defender = Fleet::new(vec![Ship::ancient(), Ship::ancient()], &bump).unwrap();
+        let log = record_battle(&attacker, &defender, &mut ChaCha8Rng::seed_from_u64(0));
+        let faces = log
+            .events
+            .iter()
+            .filter_map(|event| match event {
+                BattleEvent::DieRolled { face, .. } => Some(*face),
+                _ => None,
+            })
+            .collect::<Vec<i32>>();
+        let mut dice = ScriptedDice::new(faces).unwrap();
+        let result = replay_battle(&mut attacker.clone(), &mut defender.clone(), &mut dice);
+        assert_eq!(result.map(Some), Ok(log.result()));
+        assert!(dice.remaining().is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::{Display, Formatter};
+use itertools::Itertools;
+use crate::simulator::ShipType;
+
+/// Errors when reading or writing a fleet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FleetError {
+    /// The input is not valid JSON or does not describe a fleet
+    InvalidJson {
+        /// The field that could not be read, e.g. `ships[1].hull`. Empty if the error is not within a field
+        path: String,
+        message: String,
+    },
+    /// The fleet could not be written as JSON
+    Serialize(String),
+    /// Some ships have stats that are out of range
+    InvalidStats(Vec<StatViolation>),
+    /// The fleet has no ship of the type, e.g. to copy for a sweep
+    MissingShipType(ShipType),
+}
+
+/// A ship stat outside of the allowed range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatViolation {
+    /// The index of the ship in the fleet
+    pub ship: usize,
+    /// The stat, e.g. `hull` or `weapons[1].damage`
+    pub field: String,
+    pub value: i32,
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Display for StatViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ships[{}].{} is {} but must be ", self.ship, self.field, self.value)?;
+        match self.max {
+            i32::MAX => write!(f, "at least {}", self.min),
+            max => write!(f, "between {} and {}", self.min, max),
+        }
+    }
+}
+
+impl FleetError {
+    pub(crate) fn from_json_error(error: serde_path_to_error::Error<serde_json::Error>) -> FleetError {
+        let path = error.path().to_string();
+        FleetError::InvalidJson {
+            // The path of an error outside of any field is "."
+            path: if path == "." { String::new() } else { path },
+            message: error.into_inner().to_string(),
+        }
+    }
+}
+
+impl Display for FleetError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FleetError::InvalidJson { path, message } if path.is_empty() => write!(f, "invalid fleet: {}", message),
+            FleetError::InvalidJson { path, message } => write!(f, "invalid fleet at {}: {}", path, message),
+            FleetError::Serialize(message) => write!(f, "can not write fleet: {}", message),
+            FleetError::InvalidStats(violations) => write!(f, "invalid fleet: {}", violations.iter().join(", ")),
+            FleetError::MissingShipType(ship_type) => write!(f, "the fleet has no {:?}", ship_type),
+        }
+    }
+}
+
+impl std::error::Error for FleetError {}
+
+/// Reasons why a blueprint can not be built
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlueprintError {
+    /// NPC ships have fixed stats instead of a blueprint
+    NoBlueprint(ShipType),
+    TooManyParts {
+        ship_type: ShipType,
+        parts: usize,
+        slots: usize,
+    },
+    /// The parts use more energy than the blueprint and its sources produce
+    NotEnoughEnergy {
+        production: i32,
+        usage: i32,
+    },
+    /// Every ship except the starbase needs a drive
+    MissingDrive(ShipType),
+    /// Starbases can not move
+    DriveOnStarbase,
+}
+
+impl Display for BlueprintError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlueprintError::NoBlueprint(ship_type) => write!(f, "{:?} ships have no blueprint", ship_type),
+            BlueprintError::TooManyParts { ship_type, parts, slots } => {
+                write!(f, "{} parts do not fit the {} slots of a {:?}", parts, slots, ship_type)
+            }
+            BlueprintError::NotEnoughEnergy { production, usage } => {
+                write!(f, "the parts use {} energy but only {} is produced", usage, production)
+            }
+            BlueprintError::MissingDrive(ship_type) => write!(f, "a {:?} needs a drive", ship_type),
+            BlueprintError::DriveOnStarbase => write!(f, "a starbase can not have a drive"),
+        }
+    }
+}
+
+impl std::error::Error for BlueprintError {}
+
+/// Reasons why a dice script can not be replayed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    /// A die of the script is not a d6 result
+    InvalidFace {
+        index: usize,
+        face: i32,
+    },
+    /// The battle needed more dice than the script contains
+    OutOfDice {
+        /// The number of dice in the script, which were all rolled
+        rolled: usize,
+    },
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptError::InvalidFace { index, face } => write!(f, "die {} of the script is {} but must be between 1 and 6", index, face),
+            ScriptError::OutOfDice { rolled } => write!(f, "the script ran out of dice after {} dice", rolled),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::FleetError;
+    use crate::simulator::{Ship, ShipType, WasmFleet, Weapon};
+
+    #[test]
+    pub fn test_fleet_json_errors() {
+        let fleet = WasmFleet::new(vec![Ship::new(1, 2, 0, 1, vec![Weapon::cannon(2)], ShipType::Cruiser)]).unwrap();
+        let json = fleet.to_json().unwrap();
+        assert_eq!(WasmFleet::from_json(&json).unwrap().to_json().unwrap(), json);
+
+        let error = WasmFleet::from_json("{\"ships\": [").unwrap_err();
+        assert!(matches!(&error, FleetError::InvalidJson { path, .. } if path == "ships"), "{}", error);
+        assert!(WasmFleet::from_json("{} x").is_err());
+
+        let bad_hull = json.replacen("\"hull\":1", "\"hull\":\"one\"", 1);
+        let error = WasmFleet::from_json(&bad_hull).unwrap_err();
+        assert!(error.to_string().starts_with("invalid fleet at ships[0].hull: invalid type: string \"one\""), "{}", error);
+
+        let missing_kind = json.replacen("\"kind\":\"Cannon\",", "", 1);
+        let error = WasmFleet::from_json(&missing_kind).unwrap_err();
+        assert!(error.to_string().contains("ships[0].weapons[0]") && error.to_string().contains("missing field `kind`"), "{}", error);
+    }
+}
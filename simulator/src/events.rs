@@ -0,0 +1,193 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use bumpalo::Bump;
+use crate::dice::Dice;
+use serde::{Deserialize, Serialize};
+use crate::error::FleetError;
+use crate::simulator::{simulate_battle_rounds, BattleResult, Fleet, Ship, Side, WeaponKind};
+
+/// Something that happened during a recorded battle.
+/// Ships are given by their index in the fleets of [`BattleEvent::BattleStart`], which are sorted by initiative
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event")]
+pub enum BattleEvent {
+    BattleStart {
+        attacker: Vec<Ship>,
+        defender: Vec<Ship>,
+    },
+    /// Round 0 is the missile round, it only happens if a fleet has missiles
+    RoundStart {
+        round: u32,
+    },
+    /// The fleet starts to retreat and does not attack in this round
+    Retreat {
+        side: Side,
+    },
+    /// The ships of a fleet with the given initiative fire at the same time
+    InitiativeStep {
+        side: Side,
+        initiative: i32,
+    },
+    /// A die of a weapon was rolled. The computer is added to the face, except for a 1 or 6 and rift dice.
+    /// For rift dice, the damage is the damage of the face to the target
+    DieRolled {
+        side: Side,
+        ship: usize,
+        kind: WeaponKind,
+        face: i32,
+        computer: i32,
+        damage: i32,
+    },
+    /// A roll of the opposing fleet was assigned to a ship of this fleet. The roll counts the DieRolled events
+    /// of the opposing fleet since its last InitiativeStep, from 0. A splittable roll can be assigned to several ships
+    Assigned {
+        side: Side,
+        roll: usize,
+        target: usize,
+        damage: i32,
+    },
+    /// A ship lost hull, the remaining hull is below 0 if the ship was destroyed.
+    /// Self inflicted damage comes from the rift dice of the ship
+    Damage {
+        side: Side,
+        ship: usize,
+        damage: i32,
+        hull: i32,
+        self_inflicted: bool,
+    },
+    Destroyed {
+        side: Side,
+        ship: usize,
+    },
+    BattleEnd {
+        result: BattleResult,
+        /// The number of engagement rounds, not counting the missile round
+        rounds: u32,
+    },
+}
+
+/// Collects the events of both fleets of a battle
+#[derive(Debug, Clone)]
+pub(crate) struct Recorder {
+    events: Rc<RefCell<Vec<BattleEvent>>>,
+    pub(crate) side: Side,
+}
+
+impl Recorder {
+    pub(crate) fn record(&self, event: BattleEvent) {
+        self.events.borrow_mut().push(event);
+    }
+}
+
+/// The events of a single battle, in the order they happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BattleLog {
+    pub events: Vec<BattleEvent>,
+}
+
+impl BattleLog {
+    pub fn result(&self) -> Option<BattleResult> {
+        self.events.iter().rev().find_map(|event| match event {
+            BattleEvent::BattleEnd { result, .. } => Some(*result),
+            _ => None,
+        })
+    }
+
+    pub fn to_json(&self) -> Result<String, FleetError> {
+        serde_json::to_string(&self).map_err(|error| FleetError::Serialize(error.to_string()))
+    }
+}
+
+/// Simulates a single battle and records everything that happens in it.
+/// The fleets are not changed, the battle is fought by copies of them.
+/// Recording is slower than simulating, so it is meant for sample battles and debugging
+pub fn record_battle<T: Dice + Clone>(attacker: &Fleet, defender: &Fleet, rng: &mut T) -> BattleLog {
+    let bump = Bump::new();
+    let events = Rc::new(RefCell::new(vec![BattleEvent::BattleStart {
+        attacker: attacker.ships.to_vec(),
+        defender: defender.ships.to_vec(),
+    }]));
+    let recorder = |side: Side| Some(Recorder { events: events.clone(), side });
+    let mut attacker = attacker.clone();
+    attacker.recorder = recorder(Side::Attacker);
+    let mut defender = defender.clone();
+    defender.recorder = recorder(Side::Defender);
+
+    let (result, rounds) = simulate_battle_rounds(&mut attacker, &mut defender, rng, &bump);
+    drop((attacker, defender));
+    let mut events = Rc::try_unwrap(events).expect("The fleets of the battle were dropped").into_inner();
+    events.push(BattleEvent::BattleEnd { result, rounds });
+    BattleLog { events }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::events::{record_battle, BattleEvent, BattleLog};
+    use crate::simulator::{simulate_battle, BattleResult, Fleet, Ship, ShipType, Side, Weapon, WeaponKind};
+
+    #[test]
+    pub fn test_record_battle() {
+        let bump = bumpalo::Bump::new();
+        let interceptor = Ship::new(0, 3, 0, 1, vec![Weapon::cannon(1), Weapon::missile(1)], ShipType::Interceptor);
+        let cruiser = Ship::new(2, 2, 1, 1, vec![Weapon::cannon(2), Weapon::rift_cannon()], ShipType::Cruiser);
+        let attacker = Fleet::new(vec![interceptor.clone(), interceptor], &bump).unwrap();
+        let defender = Fleet::new(vec![cruiser], &bump).unwrap();
+
+        let log = record_battle(&attacker, &defender, &mut ChaCha8Rng::seed_from_u64(3));
+        // Recording does not change the battle
+        let result = simulate_battle(&mut attacker.clone(), &mut defender.clone(), &mut ChaCha8Rng::seed_from_u64(3));
+        assert_eq!(log.result(), Some(result));
+        assert!(matches!(log.events[0], BattleEvent::BattleStart { .. }));
+        assert_eq!(log.events[1], BattleEvent::RoundStart { round: 0 });
+
+        // Two missile dice per interceptor, the defender has no missiles
+        let missiles = log.events.iter().filter(|event| matches!(event, BattleEvent::DieRolled { kind: WeaponKind::Missile, .. })).count();
+        assert_eq!(missiles, 4);
+        for event in log.events.iter() {
+            match event {
+                BattleEvent::DieRolled { face, .. } => assert!((1..=6).contains(face)),
+                BattleEvent::Damage { damage, .. } => assert!(*damage > 0),
+                _ => {}
+            }
+        }
+        // The loser lost all ships, and every ship was destroyed by damage that left it below 0 hull
+        let destroyed = |side: Side| log.events.iter().filter(|event| matches!(event, BattleEvent::Destroyed { side: s, .. } if *s == side)).count();
+        match result {
+            BattleResult::AttackerWins => assert_eq!(destroyed(Side::Defender), 1),
+            BattleResult::DefenderWins => assert_eq!(destroyed(Side::Attacker), 2),
+            _ => {}
+        }
+        for (index, event) in log.events.iter().enumerate() {
+            if let BattleEvent::Destroyed { side, ship } = event {
+                assert!(matches!(log.events[index - 1], BattleEvent::Damage { side: s, ship: i, hull, .. } if s == *side && i == *ship && hull < 0));
+            }
+        }
+        // Every hit is assigned before the damage it deals, the roll is a die of the opposing fleet in the same initiative step
+        let mut dice = Vec::new();
+        let mut assigned = Vec::new();
+        for event in log.events.iter() {
+            match event {
+                BattleEvent::InitiativeStep { .. } => dice.clear(),
+                BattleEvent::DieRolled { side, damage, .. } => dice.push((*side, *damage)),
+                BattleEvent::Assigned { side, roll, target, damage } => {
+                    let (die_side, die_damage) = dice[*roll];
+                    assert!(die_side != *side && (1..=die_damage).contains(damage));
+                    assigned.push((*side, *target, *damage));
+                }
+                BattleEvent::Damage { side, ship, damage, self_inflicted: false, .. } => {
+                    assert!(!assigned.is_empty() && assigned.iter().all(|(s, target, _)| s == side && target == ship));
+                    assert!(assigned.iter().map(|(.., damage)| damage).sum::<i32>() >= *damage);
+                    assigned.clear();
+                }
+                _ => {}
+            }
+        }
+        assert!(log.events.iter().any(|event| matches!(event, BattleEvent::Assigned { .. })));
+
+        let json = log.to_json().unwrap();
+        assert!(json.contains("\"event\":\"DieRolled\""));
+        assert_eq!(serde_json::from_str::<BattleLog>(&json).unwrap(), log);
+    }
+}
@@ -0,0 +1,506 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use bumpalo::Bump;
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::simulator::{AttackPool, AttackRoll, BattleResult, Fleet, Phase, RetreatPolicy, Ship, WeaponKind, RIFT_DIE_FACES};
+
+/// The exact probability of every outcome of a battle
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BattleProbabilities {
+    pub attacker_wins: f64,
+    pub defender_wins: f64,
+    pub draw: f64,
+    pub attacker_retreats: f64,
+    pub defender_retreats: f64,
+}
+
+impl BattleProbabilities {
+    pub fn probability(&self, result: BattleResult) -> f64 {
+        match result {
+            BattleResult::AttackerWins => self.attacker_wins,
+            BattleResult::DefenderWins => self.defender_wins,
+            BattleResult::Draw => self.draw,
+            BattleResult::AttackerRetreats => self.attacker_retreats,
+            BattleResult::DefenderRetreats => self.defender_retreats,
+        }
+    }
+
+    fn add(&mut self, result: BattleResult, probability: f64) {
+        match result {
+            BattleResult::AttackerWins => self.attacker_wins += probability,
+            BattleResult::DefenderWins => self.defender_wins += probability,
+            BattleResult::Draw => self.draw += probability,
+            BattleResult::AttackerRetreats => self.attacker_retreats += probability,
+            BattleResult::DefenderRetreats => self.defender_retreats += probability,
+        }
+    }
+}
+
+/// The remaining hull of both fleets
+type Hulls = (Vec<i32>, Vec<i32>);
+
+/// The state within an engagement round, before the ships with the given initiatives fire
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct StepState {
+    hulls: Hulls,
+    best_attack_init: i32,
+    best_defend_init: i32,
+}
+
+/// Dice that are rolled together and only differ in the face they show
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DiceGroup {
+    Cannon { damage: i32, computer: i32, splittable: bool, dice: u32 },
+    /// Rift dice of a single ship, since some faces damage the firing ship
+    Rift { ship: usize, dice: u32 },
+}
+
+/// A distinct result of a single die together with its probability in sixths
+#[derive(Debug, Clone, Copy)]
+struct Face {
+    sixths: u32,
+    roll: Option<(i32, i32)>,
+    self_damage: i32,
+}
+
+/// Computes the exact probabilities of all battle outcomes, without sampling.
+/// The battle is a Markov chain over the remaining hull of all ships:
+/// within an engagement round, the dice of every initiative step are enumerated and the
+/// resulting states are propagated with their probability. Since hull never increases,
+/// the only cycles are rounds that change nothing, which are resolved analytically.
+/// Battles that can never end (e.g. both fleets unarmed) are counted as a draw
+pub fn solve_battle(attacker: &Fleet, defender: &Fleet) -> BattleProbabilities {
+    let mut solver = Solver {
+        attacker: attacker.clone(),
+        defender: defender.clone(),
+        bump: Bump::new(),
+        // Only the number of rounds up to the latest retreat matters
+        max_rounds: [attacker.retreat, defender.retreat]
+            .iter()
+            .map(|retreat| match retreat {
+                RetreatPolicy::AfterRound(rounds) => *rounds,
+                _ => 0,
+            })
+            .max()
+            .unwrap(),
+    };
+    solver.attacker.retreating = false;
+    solver.defender.retreating = false;
+    // The solver explores every outcome, which must not be recorded
+    solver.attacker.recorder = None;
+    solver.defender.recorder = None;
+    let mut probabilities = BattleProbabilities::default();
+    if attacker.is_at_peace_with(defender) {
+        probabilities.add(BattleResult::Draw, 1.);
+        return probabilities;
+    }
+    let start = (attacker.hull.to_vec(), defender.hull.to_vec());
+
+    // Round states are processed in an order in which no state can be reached from a later one:
+    // a round either lowers the total hull or only increases the number of rounds fought
+    let mut pending: BTreeMap<(Reverse<i32>, u32, Hulls), f64> = BTreeMap::new();
+    for (hulls, probability) in solver.engagement_round(&start, Phase::Missiles) {
+        pending.insert((Reverse(total_hull(&hulls)), 0, hulls), probability);
+    }
+
+    while let Some(((_, rounds, hulls), probability)) = pending.pop_first() {
+        solver.set_hulls(&hulls);
+        if !solver.attacker.has_ships_left() || !solver.defender.has_ships_left() {
+            probabilities.add(solver.result(), probability);
+            continue;
+        }
+        solver.attacker.retreating = solver.attacker.wants_to_retreat(rounds);
+        solver.defender.retreating = !solver.attacker.retreating && solver.defender.wants_to_retreat(rounds);
+        let (attacker_retreating, defender_retreating) = (solver.attacker.retreating, solver.defender.retreating);
+        let next_rounds = (rounds + 1).min(solver.max_rounds);
+
+        let mut transitions = solver.engagement_round(&hulls, Phase::Cannons);
+        let unchanged = if next_rounds == rounds && !attacker_retreating && !defender_retreating {
+            transitions.remove(&hulls).unwrap_or(0.)
+        } else {
+            0.
+        };
+        if unchanged >= 1. - 1e-12 {
+            // No ship can ever be damaged again, the battle does not end
+            probabilities.draw += probability;
+            continue;
+        }
+        // Repeating the round until something changes
+        let probability = probability / (1. - unchanged);
+
+        for (next, transition) in transitions {
+            solver.set_hulls(&next);
+            if attacker_retreating && solver.attacker.has_ships_left() {
+                probabilities.attacker_retreats += probability * transition;
+            } else if defender_retreating && solver.defender.has_ships_left() {
+                probabilities.defender_retreats += probability * transition;
+            } else {
+                let key = (Reverse(total_hull(&next)), next_rounds, next);
+                *pending.entry(key).or_insert(0.) += probability * transition;
+            }
+        }
+    }
+    probabilities
+}
+
+fn total_hull((attacker, defender): &Hulls) -> i32 {
+    attacker.iter().chain(defender.iter()).map(|hull| hull.max(&-1)).sum()
+}
+
+struct Solver<'a> {
+    /// Working copies of the fleets, their hull is overwritten with the state that is expanded
+    attacker: Fleet<'a>,
+    defender: Fleet<'a>,
+    bump: Bump,
+    max_rounds: u32,
+}
+
+impl Solver<'_> {
+    fn set_hulls(&mut self, (attacker, defender): &Hulls) {
+        self.attacker.hull.copy_from_slice(attacker);
+        self.defender.hull.copy_from_slice(defender);
+    }
+
+    fn hulls(&self) -> Hulls {
+        (self.attacker.hull.to_vec(), self.defender.hull.to_vec())
+    }
+
+    /// The result of a battle in which at least one fleet has no ships left
+    fn result(&self) -> BattleResult {
+        match (self.attacker.has_ships_left(), self.defender.has_ships_left()) {
+            (false, false) => BattleResult::Draw,
+            (false, true) => BattleResult::DefenderWins,
+            _ => BattleResult::AttackerWins,
+        }
+    }
+
+    /// The distribution of the hulls after an engagement round, mirroring the simulator:
+    /// the ships fire in initiative steps, on a draw the defender fires first
+    fn engagement_round(&mut self, hulls: &Hulls, phase: Phase) -> HashMap<Hulls, f64> {
+        let mut finished = HashMap::new();
+        self.set_hulls(hulls);
+        if !self.attacker.has_ships_left() || !self.defender.has_ships_left() {
+            finished.insert(hulls.clone(), 1.);
+            return finished;
+        }
+        let mut steps = HashMap::new();
+        steps.insert(StepState {
+            hulls: hulls.clone(),
+            best_attack_init: first_initiative(&self.attacker, i32::MAX),
+            best_defend_init: first_initiative(&self.defender, i32::MAX),
+        }, 1.);
+
+        while !steps.is_empty() {
+            let mut next_steps: HashMap<StepState, f64> = HashMap::new();
+            for (step, probability) in steps {
+                self.set_hulls(&step.hulls);
+                if !self.attacker.has_ships_left()
+                    || !self.defender.has_ships_left()
+                    || (step.best_attack_init < 0 && step.best_defend_init < 0)
+                {
+                    *finished.entry(step.hulls).or_insert(0.) += probability;
+                    continue;
+                }
+                for (next, outcome) in self.initiative_step(&step, phase) {
+                    *next_steps.entry(next).or_insert(0.) += probability * outcome;
+                }
+            }
+            steps = next_steps;
+        }
+        finished
+    }
+
+    /// Fires all ships of one initiative step for every combination of dice results
+    fn initiative_step(&mut self, step: &StepState, phase: Phase) -> Vec<(StepState, f64)> {
+        let attacker_fires = step.best_attack_init > step.best_defend_init;
+        let (firing, target) = if attacker_fires {
+            (&self.attacker, &self.defender)
+        } else {
+            (&self.defender, &self.attacker)
+        };
+        let (best_init, lowest_init) = if attacker_fires {
+            // The defender fires first on a draw, so only higher initiatives fire now
+            (step.best_attack_init, step.best_defend_init + 1)
+        } else {
+            (step.best_defend_init, step.best_attack_init)
+        };
+        let ships = (0..firing.ships.len())
+            .filter(|i| firing.hull[*i] >= 0)
+            .filter(|i| (lowest_init..=best_init).contains(&firing.ships[*i].initiative))
+            .collect::<Vec<usize>>();
+        let next_init = first_initiative(firing, lowest_init - 1);
+        let (best_attack_init, best_defend_init) = if attacker_fires {
+            (next_init, step.best_defend_init)
+        } else {
+            (step.best_attack_init, next_init)
+        };
+
+        // Retreating ships lose their attack
+        let groups = if firing.retreating {
+            Vec::new()
+        } else {
+            dice_groups(ships.iter().map(|i| (*i, &firing.ships[*i])), phase)
+        };
+        let faces = groups.iter().map(|group| group_faces(group, target)).collect::<Vec<Vec<Face>>>();
+
+        let mut outcomes = Vec::new();
+        let mut counts = Vec::with_capacity(groups.len());
+        self.enumerate(&groups, &faces, &mut counts, 1., attacker_fires, &mut |hulls, probability| {
+            outcomes.push((StepState { hulls, best_attack_init, best_defend_init }, probability));
+        });
+        outcomes
+    }
+
+    /// Enumerates the number of dice of each group that show each face
+    fn enumerate(
+        &mut self,
+        groups: &[DiceGroup],
+        faces: &[Vec<Face>],
+        counts: &mut Vec<Vec<u32>>,
+        probability: f64,
+        attacker_fires: bool,
+        found: &mut impl FnMut(Hulls, f64),
+    ) {
+        if counts.len() == groups.len() {
+            let hulls = self.hulls();
+            self.fire(groups, faces, counts, attacker_fires);
+            found(self.hulls(), probability);
+            self.set_hulls(&hulls);
+            return;
+        }
+        let group_faces = &faces[counts.len()];
+        let dice = match groups[counts.len()] {
+            DiceGroup::Cannon { dice, .. } | DiceGroup::Rift { dice, .. } => dice,
+        };
+        for face_counts in compositions(dice, group_faces.len()) {
+            let outcome = multinomial(dice, &face_counts)
+                * face_counts
+                    .iter()
+                    .zip(group_faces.iter())
+                    .map(|(count, face)| (face.sixths as f64 / 6.).powi(*count as i32))
+                    .product::<f64>();
+            counts.push(face_counts);
+            self.enumerate(groups, faces, counts, probability * outcome, attacker_fires, found);
+            counts.pop();
+        }
+    }
+
+    /// Applies one combination of dice results through the attack pool of the simulator
+    fn fire(&mut self, groups: &[DiceGroup], faces: &[Vec<Face>], counts: &[Vec<u32>], attacker_fires: bool) {
+        self.bump.reset();
+        let mut pool = AttackPool::new_in(&self.bump);
+        for ((group, group_faces), face_counts) in groups.iter().zip(faces.iter()).zip(counts.iter()) {
+            for (face, count) in group_faces.iter().zip(face_counts.iter()) {
+                for _ in 0..*count {
+                    if let Some((damage, hit_dc)) = face.roll {
+                        let splittable = matches!(group, DiceGroup::Cannon { splittable: true, .. });
+                        pool.enhanced_rolls.push(AttackRoll { damage, hit_dc, splittable });
+                    }
+                    if let DiceGroup::Rift { ship, .. } = group {
+                        if face.self_damage > 0 {
+                            pool.self_damage.push((*ship, face.self_damage));
+                        }
+                    }
+                }
+            }
+        }
+        let (firing, target) = if attacker_fires {
+            (&mut self.attacker, &mut self.defender)
+        } else {
+            (&mut self.defender, &mut self.attacker)
+        };
+        pool.attack_fleet(target, firing.targeting.as_ref(), &self.bump);
+        pool.damage_own_fleet(firing);
+    }
+}
+
+/// The initiative of the first ship that is alive and has at most the given initiative, or -1
+fn first_initiative(fleet: &Fleet, max_init: i32) -> i32 {
+    fleet.ships
+        .iter()
+        .zip(fleet.hull.iter())
+        .find(|(ship, hull)| **hull >= 0 && ship.initiative <= max_init)
+        .map_or(-1, |(ship, _)| ship.initiative)
+}
+
+/// Groups the dice of the firing ships in the same way the simulator rolls them
+fn dice_groups<'s>(ships: impl Iterator<Item=(usize, &'s Ship)>, phase: Phase) -> Vec<DiceGroup> {
+    let mut groups: Vec<DiceGroup> = Vec::new();
+    for (index, ship) in ships {
+        for weapon in ship.weapons.iter() {
+            let group = match (phase, weapon.kind) {
+                (Phase::Cannons, WeaponKind::Rift) => DiceGroup::Rift { ship: index, dice: 0 },
+                (Phase::Cannons, WeaponKind::Cannon) | (Phase::Missiles, WeaponKind::Missile) if weapon.damage > 0 => DiceGroup::Cannon {
+                    damage: weapon.damage,
+                    computer: ship.computer,
+                    splittable: weapon.splittable,
+                    dice: 0,
+                },
+                _ => continue,
+            };
+            let dice = weapon.dice.max(0) as u32;
+            let same_dice = |existing: &DiceGroup| match (existing, &group) {
+                (DiceGroup::Rift { ship: a, .. }, DiceGroup::Rift { ship: b, .. }) => a == b,
+                (
+                    DiceGroup::Cannon { damage, computer, splittable, .. },
+                    DiceGroup::Cannon { damage: d, computer: c, splittable: s, .. },
+                ) => (damage, computer, splittable) == (d, c, s),
+                _ => false,
+            };
+            let position = groups.iter().position(same_dice).unwrap_or_else(|| {
+                groups.push(group.clone());
+                groups.len() - 1
+            });
+            match &mut groups[position] {
+                DiceGroup::Cannon { dice: total, .. } | DiceGroup::Rift { dice: total, .. } => *total += dice,
+            }
+        }
+    }
+    groups
+}
+
+/// The distinct results of a die against the target fleet.
+/// Faces of a cannon die that hit the same ships are merged
+fn group_faces(group: &DiceGroup, target: &Fleet) -> Vec<Face> {
+    match *group {
+        DiceGroup::Rift { .. } => {
+            let mut faces: Vec<Face> = Vec::new();
+            for (damage, self_damage) in RIFT_DIE_FACES {
+                let roll = (damage > 0).then_some((damage, 1_000_000));
+                match faces.iter_mut().find(|face| face.roll == roll && face.self_damage == self_damage) {
+                    Some(face) => face.sixths += 1,
+                    None => faces.push(Face { sixths: 1, roll, self_damage }),
+                }
+            }
+            faces
+        }
+        DiceGroup::Cannon { damage, computer, .. } => {
+            let shields = (0..target.ships.len())
+                .filter(|i| target.hull[*i] >= 0)
+                .map(|i| target.ships[i].shield)
+                .collect::<Vec<i32>>();
+            let mut faces: Vec<Face> = Vec::new();
+            for roll in 1..=6 {
+                let hit_dc = match roll {
+                    6 => 1_000_000,
+                    1 => -1_000_000,
+                    _ => roll + computer,
+                };
+                // Use the lowest shield that is still hit as the canonical roll
+                let roll = shields
+                    .iter()
+                    .filter(|shield| hit_dc >= *shield + 6)
+                    .max()
+                    .map(|shield| (damage, if shields.iter().all(|s| hit_dc >= s + 6) { 1_000_000 } else { shield + 6 }));
+                match faces.iter_mut().find(|face| face.roll == roll) {
+                    Some(face) => face.sixths += 1,
+                    None => faces.push(Face { sixths: 1, roll, self_damage: 0 }),
+                }
+            }
+            faces
+        }
+    }
+}
+
+/// All ways to distribute the dice among the faces
+fn compositions(dice: u32, faces: usize) -> Vec<Vec<u32>> {
+    if faces == 1 {
+        return vec![vec![dice]];
+    }
+    (0..=dice)
+        .flat_map(|first| {
+            compositions(dice - first, faces - 1).into_iter().map(move |mut rest| {
+                rest.insert(0, first);
+                rest
+            })
+        })
+        .collect()
+}
+
+fn multinomial(dice: u32, counts: &[u32]) -> f64 {
+    let mut value = 1.;
+    let mut remaining = dice;
+    for count in counts {
+        // Binomial coefficient of the remaining dice
+        for i in 0..*count {
+            value *= (remaining - i) as f64 / (i + 1) as f64;
+        }
+        remaining -= count;
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::exact::solve_battle;
+    use crate::simulator::{simulate_battle_bump, BattleResult, Fleet, RetreatPolicy, Ship, ShipType, Weapon};
+
+    #[test]
+    pub fn test_single_shot() {
+        let bump = bumpalo::Bump::new();
+        // The attacker hits on a 5 or 6 and destroys the defender, which can not shoot back
+        let attacker = Fleet::new(vec![Ship::new(0, 1, 0, 0, vec![Weapon::cannon(1)], ShipType::Interceptor)], &bump).unwrap();
+        let defender = Fleet::new(vec![Ship::new(0, 0, 1, 0, vec![], ShipType::Interceptor)], &bump).unwrap();
+        let probabilities = solve_battle(&attacker, &defender);
+        assert!((probabilities.attacker_wins - 1.).abs() < 1e-9);
+
+        // Both ships hit on a 6, the defender fires first
+        let ship = Ship::new(0, 0, 0, 0, vec![Weapon::cannon(1)], ShipType::Interceptor);
+        let attacker = Fleet::new(vec![ship.clone()], &bump).unwrap();
+        let defender = Fleet::new(vec![ship], &bump).unwrap();
+        let probabilities = solve_battle(&attacker, &defender);
+        // The defender wins with 1/6 + (5/6)^2 * 1/6 + ...
+        assert!((probabilities.defender_wins - 6. / 11.).abs() < 1e-9);
+        assert!((probabilities.attacker_wins - 5. / 11.).abs() < 1e-9);
+    }
+
+    /// Compares the solution with the frequencies of simulated battles
+    fn assert_matches_simulation(attacker: &Fleet, defender: &Fleet, n: usize) {
+        let bump = bumpalo::Bump::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let probabilities = solve_battle(attacker, defender);
+        let total = probabilities.attacker_wins + probabilities.defender_wins + probabilities.draw
+            + probabilities.attacker_retreats + probabilities.defender_retreats;
+        assert!((total - 1.).abs() < 1e-9);
+
+        let mut counts = [0; 5];
+        let results = [BattleResult::AttackerWins, BattleResult::DefenderWins, BattleResult::Draw, BattleResult::AttackerRetreats, BattleResult::DefenderRetreats];
+        for _ in 0..n {
+            let result = simulate_battle_bump(&mut attacker.clone(), &mut defender.clone(), &mut rng, &bump);
+            counts[results.iter().position(|r| *r == result).unwrap()] += 1;
+        }
+        for (result, count) in results.iter().zip(counts) {
+            let expected = probabilities.probability(*result);
+            // Well within four standard deviations of the sampled frequency
+            let tolerance = 4. * (expected * (1. - expected) / n as f64).sqrt() + 1e-3;
+            assert!((count as f64 / n as f64 - expected).abs() < tolerance, "{:?}: {} vs {}", result, count, expected);
+        }
+    }
+
+    #[test]
+    pub fn test_matches_simulation() {
+        let bump = bumpalo::Bump::new();
+        let interceptor = Ship::new(1, 3, 0, 1, vec![Weapon::cannon(1), Weapon::missile(2)], ShipType::Interceptor);
+        let cruiser = Ship::new(2, 2, 1, 1, vec![Weapon::cannon(2), Weapon::rift_cannon()], ShipType::Cruiser);
+        let starbase = Ship::new(2, 4, 1, 2, vec![Weapon::cannon(1), Weapon::cannon(4).with_split_damage()], ShipType::Starbase);
+        let attacker = Fleet::new(vec![interceptor.clone(), interceptor, cruiser.clone()], &bump).unwrap()
+            .with_retreat(RetreatPolicy::AfterRound(2));
+        let defender = Fleet::new(vec![starbase, cruiser], &bump).unwrap();
+        assert_matches_simulation(&attacker, &defender, 50_000);
+    }
+
+    #[test]
+    pub fn test_matches_simulation_with_mixed_shields() {
+        let bump = bumpalo::Bump::new();
+        // A 5 only hits the unshielded cruiser, a 6 hits both ships.
+        // Which roll is spent on the cruiser must not depend on the order the dice were rolled in
+        let attacker = Fleet::new(vec![Ship::new(0, 1, 0, 1, vec![Weapon::cannon(1); 2], ShipType::Interceptor)], &bump).unwrap();
+        let cruiser = Ship::new(0, 0, 0, 0, vec![], ShipType::Cruiser);
+        let shielded = Ship::new(0, 0, 1, 0, vec![Weapon::cannon(1)], ShipType::Interceptor);
+        let defender = Fleet::new(vec![cruiser, shielded], &bump).unwrap();
+        assert_matches_simulation(&attacker, &defender, 50_000);
+    }
+}
@@ -1,22 +1,61 @@
-use rand::rngs::{StdRng};
-use rand::{SeedableRng};
+use rand::SeedableRng;
 use rand_chacha::ChaCha8Rng;
 use wasm_bindgen::prelude::*;
 
 mod simulator;
-pub use simulator::{BattleResult, Fleet, Ship, ShipType};
+mod sector;
+mod npc;
+mod targeting;
+mod exact;
+mod error;
+mod statistics;
+mod validation;
+mod blueprint;
+mod species;
+mod advisor;
+mod composition;
+mod sweep;
+mod events;
+mod dice;
+#[cfg(not(target_arch = "wasm32"))]
+mod parallel;
+pub use simulator::{BattleResult, Fleet, HitEdge, HitGraph, RetreatPolicy, Ship, ShipType, Side, WasmFleet, Weapon, WeaponKind};
+pub use sector::{simulate_sector, SectorBattle, SectorResult};
+pub use exact::BattleProbabilities;
+pub use advisor::{rank_upgrades, Upgrade};
+pub use events::{BattleEvent, BattleLog};
+pub use sweep::{Sweep, SweepParameter, SweepPoint};
+pub use composition::{rank_compositions, Composition};
+pub use blueprint::{Blueprint, Part, PartStats};
+pub use species::{CombatModifiers, Species};
+pub use dice::{Dice, ScriptedDice};
+pub use error::{BlueprintError, FleetError, ScriptError, StatViolation};
+pub use validation::validate_ships;
+pub use statistics::{BattleStatistics, ConfidenceInterval, ShipTypeCounts, SurvivorHistogram};
+pub use targeting::{BiggestThreat, FocusStarbase, MostKills, NpcRule, Objective, OptimalAssignment, SpreadDamage, Targeting, TargetingStrategy};
 
 #[cfg(not(target_arch = "wasm32"))]
-pub use simulator::{simulate_battle, simulate_round, simulate_battle_bump, simulate_n_battles};
-use crate::simulator::WasmFleet;
+pub use simulator::{simulate_battle, simulate_round, simulate_battle_bump, simulate_n_battles, simulate_until_precision};
+#[cfg(not(target_arch = "wasm32"))]
+pub use exact::solve_battle;
+#[cfg(not(target_arch = "wasm32"))]
+pub use parallel::simulate_n_battles_parallel;
+#[cfg(not(target_arch = "wasm32"))]
+pub use sweep::sweep;
+#[cfg(not(target_arch = "wasm32"))]
+pub use events::record_battle;
+#[cfg(not(target_arch = "wasm32"))]
+pub use dice::replay_battle;
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "console_log")] {
+        #[allow(dead_code)]
         fn init_log() {
             use log::Level;
             console_log::init_with_level(Level::Trace).expect("error initializing log");
         }
     } else {
+        #[allow(dead_code)]
         fn init_log() {
             //Init env logger with default logging trace and info values
             env_logger::builder()
@@ -31,6 +70,7 @@ cfg_if::cfg_if! {
 
 #[wasm_bindgen]
 pub struct RngState {
+    #[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
     rng_state: ChaCha8Rng,
 }
 
@@ -55,12 +95,28 @@ pub fn simulate_n_battles(
     defender: &WasmFleet,
     n: usize,
     rng: &mut RngState,
-) -> f32 {
+) -> BattleStatistics {
     let bump = bumpalo::Bump::new();
     let attacker = attacker.clone().into_fleet(&bump);
     let defender = defender.clone().into_fleet(&bump);
     simulator::simulate_n_battles(attacker,  defender, &mut rng.rng_state, n, &bump)
 }
+/// Simulates battles until the confidence interval of the defender win rate is narrower than epsilon,
+/// or until max_battles have been simulated
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn simulate_until_precision(
+    attacker: &WasmFleet,
+    defender: &WasmFleet,
+    epsilon: f64,
+    max_battles: usize,
+    rng: &mut RngState,
+) -> BattleStatistics {
+    let bump = bumpalo::Bump::new();
+    let attacker = attacker.clone().into_fleet(&bump);
+    let defender = defender.clone().into_fleet(&bump);
+    simulator::simulate_until_precision(attacker, defender, &mut rng.rng_state, epsilon, max_battles, &bump)
+}
 #[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 pub fn simulate_battle(
@@ -74,6 +130,50 @@ pub fn simulate_battle(
     simulator::simulate_battle(&mut attacker, &mut defender, &mut rng.rng_state)
 }
 
+/// Computes the exact probabilities of all battle outcomes
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn solve_battle(attacker: &WasmFleet, defender: &WasmFleet) -> BattleProbabilities {
+    let bump = bumpalo::Bump::new();
+    let attacker = attacker.clone().into_fleet(&bump);
+    let defender = defender.clone().into_fleet(&bump);
+    exact::solve_battle(&attacker, &defender)
+}
+
+/// Simulates a single battle and returns its events as JSON, e.g. to animate it
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn record_battle(attacker: &WasmFleet, defender: &WasmFleet, rng: &mut RngState) -> Result<String, JsError> {
+    let bump = bumpalo::Bump::new();
+    let attacker = attacker.clone().into_fleet(&bump);
+    let defender = defender.clone().into_fleet(&bump);
+    Ok(events::record_battle(&attacker, &defender, &mut rng.rng_state).to_json()?)
+}
+
+/// Simulates a single battle with the given d6 results instead of random dice, see [`ScriptedDice`]
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn replay_battle(attacker: &WasmFleet, defender: &WasmFleet, faces: Vec<i32>) -> Result<BattleResult, JsError> {
+    let bump = bumpalo::Bump::new();
+    let mut attacker = attacker.clone().into_fleet(&bump);
+    let mut defender = defender.clone().into_fleet(&bump);
+    let mut dice = ScriptedDice::new(faces)?;
+    Ok(dice::replay_battle(&mut attacker, &mut defender, &mut dice)?)
+}
+
+/// Simulates n battles for every value of the sweep, with the same seed for every point
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn sweep(
+    attacker: &WasmFleet,
+    defender: &WasmFleet,
+    sweep: &Sweep,
+    n: usize,
+    seed: u64,
+) -> Result<Vec<SweepPoint>, JsError> {
+    Ok(sweep::sweep(attacker, defender, sweep, n, seed)?)
+}
+
 #[cfg(target_arch = "wasm32")]
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
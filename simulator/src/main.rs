@@ -1,60 +1,205 @@
-use bumpalo::Bump;
-use log::info;
-use rand::prelude::StdRng;
-use rand::SeedableRng;
-use rand_chacha::ChaCha8Rng;
-use eclipse_sim::{simulate_battle, simulate_battle_bump, simulate_n_battles, BattleResult, Fleet, Ship, ShipType};
+use std::path::{Path, PathBuf};
+use clap::{Parser, ValueEnum};
+use serde_json::{json, Value};
+use eclipse_sim::{simulate_n_battles_parallel, sweep, BattleStatistics, ShipType, Side, SurvivorHistogram, Sweep, SweepParameter, SweepPoint, WasmFleet};
+
+/// Simulates battles between two fleets and prints the outcome statistics.
+/// Fleets are read from JSON or TOML files in the WasmFleet format, the format is chosen by the file extension
+#[derive(Parser, Debug)]
+#[command(version)]
+struct Args {
+    /// The fleet that enters the sector
+    attacker: PathBuf,
+    /// The fleet that holds the sector
+    defender: PathBuf,
+    /// Number of battles to simulate
+    #[arg(short = 'n', long, default_value_t = 100_000)]
+    iterations: usize,
+    #[arg(short, long, default_value_t = 0)]
+    seed: u64,
+    /// Number of threads, 0 uses one thread per core.
+    /// The results are reproducible for the same seed and number of threads
+    #[arg(short, long, default_value_t = 1)]
+    threads: usize,
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// Varies one stat or the number of ships of a type and prints the statistics of every value as CSV,
+    /// e.g. `defender.interceptor.shield=0..3` or `attacker.cruiser.ship_count=1..4`.
+    /// The points are simulated on one thread, each with the same seed
+    #[arg(long, value_parser = parse_sweep)]
+    sweep: Option<Sweep>,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+}
+
+const SHIP_TYPES: [ShipType; 7] = [
+    ShipType::Interceptor,
+    ShipType::Cruiser,
+    ShipType::Dreadnought,
+    ShipType::Starbase,
+    ShipType::Ancient,
+    ShipType::Guardian,
+    ShipType::Gcds,
+];
 
 fn main() {
-    env_logger::builder()
-        .filter_level(log::LevelFilter::Info)
-        .init();
-    let bump = Bump::new();
-    // init_log();
-    // let mut rng = StdRng::seed_from_u64(3);
-    let mut rng = ChaCha8Rng::seed_from_u64(3);
-    let ship_proto = Ship {
-        hull: 2,
-        initiative: 0,
-        shield: 1,
-        computer: 1,
-        weapon_1_dmg: 2,
-        weapon_2_dmg: 0,
-        ship_type: ShipType::Interceptor,
+    let args = Args::parse();
+    if let Err(error) = run(&args) {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run(args: &Args) -> Result<(), String> {
+    let attacker = read_fleet(&args.attacker)?;
+    let defender = read_fleet(&args.defender)?;
+    if let Some(parameters) = &args.sweep {
+        let points = sweep(&attacker, &defender, parameters, args.iterations, args.seed).map_err(|error| error.to_string())?;
+        print_csv(&points);
+        return Ok(());
+    }
+    let statistics = simulate_n_battles_parallel(&attacker, &defender, args.iterations, args.seed, args.threads);
+
+    // Only show the ship types that take part in the battle
+    let ship_types = SHIP_TYPES
+        .into_iter()
+        .filter(|ship_type| attacker.ships().iter().chain(defender.ships()).any(|ship| ship.ship_type == *ship_type))
+        .collect::<Vec<ShipType>>();
+    match args.format {
+        Format::Table => print_table(&statistics, &ship_types),
+        Format::Json => println!("{:#}", to_json(&statistics, &ship_types)),
+    }
+    Ok(())
+}
+
+fn read_fleet(path: &Path) -> Result<WasmFleet, String> {
+    let content = std::fs::read_to_string(path).map_err(|error| format!("can not read {}: {}", path.display(), error))?;
+    let fleet = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => toml::from_str(&content).map_err(|error| error.to_string()),
+        _ => WasmFleet::from_json(&content).map_err(|error| error.to_string()),
     };
-    let ship_proto_def = Ship {
-        hull: 3,
-        initiative: 0,
-        shield: 1,
-        computer: 1,
-        weapon_1_dmg: 2,
-        weapon_2_dmg: 0,
-        ship_type: ShipType::Interceptor,
+    fleet.map_err(|error| format!("invalid fleet in {}: {}", path.display(), error))
+}
+
+/// Parses `side.ship_type.parameter=from..to`, the names are case insensitive and may contain underscores
+fn parse_sweep(value: &str) -> Result<Sweep, String> {
+    let usage = || format!("expected side.ship_type.parameter=from..to, got {}", value);
+    let (target, range) = value.split_once('=').ok_or_else(usage)?;
+    let [side, ship_type, parameter] = target.split('.').collect::<Vec<&str>>()[..] else {
+        return Err(usage());
     };
-    let attacker_fleet = Fleet::new(vec!(ship_proto.clone(); 20), &bump);
-    let defender_fleet = Fleet::new(vec!(ship_proto_def.clone(); 20), &bump);
-
-    let mut defender_wins = 0;
-    let n = 1_000_000;
-    let result = simulate_n_battles(
-        attacker_fleet.clone(),
-        defender_fleet.clone(),
-        &mut rng,
-        n,
-        &bump,
-    );
-    // for i in 0..n {
-    //     // println!("Simulation {}", i);
-    //     let result = simulate_battle_bump(
-    //         &mut attacker_fleet.clone(),
-    //         &mut defender_fleet.clone(),
-    //         &mut rng,
-    //         &bump,
-    //     );
-    //     if result == BattleResult::DefenderWins {
-    //         defender_wins += 1;
-    //     }
-    // }
-    info!("Result: {:?}", result);
+    let (from, to) = range.split_once("..").ok_or_else(usage)?;
+    Ok(Sweep::new(
+        find_by_name(side, [Side::Attacker, Side::Defender])?,
+        find_by_name(ship_type, SHIP_TYPES)?,
+        find_by_name(parameter, SweepParameter::ALL)?,
+        from.trim().parse().map_err(|_| usage())?,
+        to.trim().parse().map_err(|_| usage())?,
+    ))
 }
 
+fn find_by_name<T: std::fmt::Debug, const N: usize>(name: &str, values: [T; N]) -> Result<T, String> {
+    let normalized = name.replace('_', "").to_lowercase();
+    let names = values.iter().map(|value| format!("{:?}", value)).collect::<Vec<String>>().join(", ");
+    values
+        .into_iter()
+        .find(|value| format!("{:?}", value).to_lowercase() == normalized)
+        .ok_or_else(|| format!("unknown name {}, expected one of {}", name, names))
+}
+
+fn print_csv(points: &[SweepPoint]) {
+    println!("value,battles,attacker_wins,defender_wins,draws,attacker_retreats,defender_retreats,defender_win_rate,defender_win_low,defender_win_high");
+    for point in points {
+        let statistics = &point.statistics;
+        let interval = statistics.defender_win_interval();
+        println!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            point.value,
+            statistics.battles,
+            statistics.attacker_wins,
+            statistics.defender_wins,
+            statistics.draws,
+            statistics.attacker_retreats,
+            statistics.defender_retreats,
+            statistics.defender_win_rate(),
+            interval.low,
+            interval.high,
+        );
+    }
+}
+
+fn percent(count: u32, battles: u32) -> String {
+    format!("{:.2}%", 100. * count as f64 / battles.max(1) as f64)
+}
+
+fn print_table(statistics: &BattleStatistics, ship_types: &[ShipType]) {
+    let interval = statistics.defender_win_interval();
+    println!("{:<20}{:>10}", "Battles", statistics.battles);
+    for (name, count) in [
+        ("Attacker wins", statistics.attacker_wins),
+        ("Defender wins", statistics.defender_wins),
+        ("Draws", statistics.draws),
+        ("Attacker retreats", statistics.attacker_retreats),
+        ("Defender retreats", statistics.defender_retreats),
+    ] {
+        println!("{:<20}{:>10}{:>10}", name, count, percent(count, statistics.battles));
+    }
+    println!("{:<20}{:>9.2}% - {:.2}%", "Defender win 95% CI", 100. * interval.low, 100. * interval.high);
+
+    println!();
+    println!("{:<20}{:>10}{:>10}", "Rounds", "Battles", "");
+    for (rounds, count) in statistics.rounds.iter().enumerate().filter(|(_, count)| **count > 0) {
+        println!("{:<20}{:>10}{:>10}", rounds, count, percent(*count, statistics.battles));
+    }
+
+    println!();
+    println!("{:<20}{:>10}{:>10}{:>10}{:>10}", "Survivors", "Attacker", "P(>=1)", "Defender", "P(>=1)");
+    for ship_type in ship_types {
+        println!(
+            "{:<20}{:>10.2}{:>9.2}%{:>10.2}{:>9.2}%",
+            format!("{:?}", ship_type),
+            statistics.expected_attacker_survivors().get(*ship_type),
+            100. * statistics.attacker_survivors.probability_at_least(*ship_type, 1),
+            statistics.expected_defender_survivors().get(*ship_type),
+            100. * statistics.defender_survivors.probability_at_least(*ship_type, 1),
+        );
+    }
+}
+
+fn survivors_json(survivors: &SurvivorHistogram, ship_types: &[ShipType]) -> Value {
+    let expected = survivors.expected();
+    ship_types
+        .iter()
+        .map(|ship_type| {
+            (format!("{:?}", ship_type), json!({
+                "expected": expected.get(*ship_type),
+                "counts": survivors.survivor_counts(*ship_type),
+                "remaining_hull": survivors.remaining_hull(*ship_type),
+            }))
+        })
+        .collect::<serde_json::Map<String, Value>>()
+        .into()
+}
+
+fn to_json(statistics: &BattleStatistics, ship_types: &[ShipType]) -> Value {
+    let interval = statistics.defender_win_interval();
+    json!({
+        "battles": statistics.battles,
+        "attacker_wins": statistics.attacker_wins,
+        "defender_wins": statistics.defender_wins,
+        "draws": statistics.draws,
+        "attacker_retreats": statistics.attacker_retreats,
+        "defender_retreats": statistics.defender_retreats,
+        "attacker_win_rate": statistics.attacker_win_rate(),
+        "defender_win_rate": statistics.defender_win_rate(),
+        "draw_rate": statistics.draw_rate(),
+        "defender_win_interval": {"low": interval.low, "high": interval.high},
+        "rounds": statistics.rounds,
+        "attacker_survivors": survivors_json(&statistics.attacker_survivors, ship_types),
+        "defender_survivors": survivors_json(&statistics.defender_survivors, ship_types),
+    })
+}
@@ -0,0 +1,80 @@
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::simulator::{Ship, ShipType, WasmFleet, Weapon, WeaponKind};
+
+/// Preset stats of the NPC ships, taken from the standard and advanced NPC tiles
+#[wasm_bindgen]
+impl Ship {
+    /// Initiative 2, one hull, +1 computer and two ion cannons
+    pub fn ancient() -> Ship {
+        Ship::new(1, 2, 0, 1, vec![Weapon::new(WeaponKind::Cannon, 1, 2)], ShipType::Ancient)
+    }
+
+    /// Initiative 1, two hull, +1 computer and a plasma cannon
+    pub fn ancient_advanced() -> Ship {
+        Ship::new(2, 1, 0, 1, vec![Weapon::cannon(2)], ShipType::Ancient)
+    }
+
+    /// Initiative 3, two hull, +2 computer and three ion cannons
+    pub fn guardian() -> Ship {
+        Ship::new(2, 3, 0, 2, vec![Weapon::new(WeaponKind::Cannon, 1, 3)], ShipType::Guardian)
+    }
+
+    /// Initiative 2, three hull, one shield, +1 computer and an antimatter cannon
+    pub fn guardian_advanced() -> Ship {
+        Ship::new(3, 2, 1, 1, vec![Weapon::cannon(4)], ShipType::Guardian)
+    }
+
+    /// Initiative 0, seven hull, +1 computer and four ion cannons
+    pub fn gcds() -> Ship {
+        Ship::new(7, 0, 0, 1, vec![Weapon::new(WeaponKind::Cannon, 1, 4)], ShipType::Gcds)
+    }
+
+    /// Initiative 2, seven hull, one shield, +2 computer, four ion cannons and two plasma missiles
+    pub fn gcds_advanced() -> Ship {
+        Ship::new(7, 2, 1, 2, vec![Weapon::new(WeaponKind::Cannon, 1, 4), Weapon::missile(2)], ShipType::Gcds)
+    }
+}
+
+/// Fleets guarding the NPC sectors
+#[wasm_bindgen]
+impl WasmFleet {
+    /// The given number of Ancient ships
+    pub fn ancients(count: usize, advanced: bool) -> WasmFleet {
+        let ship = if advanced { Ship::ancient_advanced() } else { Ship::ancient() };
+        WasmFleet::from_valid_ships(vec![ship; count])
+    }
+
+    /// A single Guardian
+    pub fn guardian(advanced: bool) -> WasmFleet {
+        let ship = if advanced { Ship::guardian_advanced() } else { Ship::guardian() };
+        WasmFleet::from_valid_ships(vec![ship])
+    }
+
+    /// The Galactic Center Defense System
+    pub fn gcds(advanced: bool) -> WasmFleet {
+        let ship = if advanced { Ship::gcds_advanced() } else { Ship::gcds() };
+        WasmFleet::from_valid_ships(vec![ship])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::simulator::{simulate_n_battles, Ship, ShipType, WasmFleet, Weapon};
+
+    #[test]
+    pub fn test_clear_ancient_sector() {
+        let bump = bumpalo::Bump::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let ancients = WasmFleet::ancients(2, false).into_fleet(&bump);
+        // Two dreadnoughts with plasma cannons should clear two Ancients most of the time
+        let dreadnought = Ship::new(2, 0, 0, 1, vec![Weapon::cannon(2), Weapon::cannon(2)], ShipType::Dreadnought);
+        let attacker = WasmFleet::new(vec![dreadnought; 2]).unwrap().into_fleet(&bump);
+        let statistics = simulate_n_battles(attacker, ancients, &mut rng, 1000, &bump);
+        assert!(statistics.defender_win_rate() < 0.5);
+        assert!(Ship::gcds().ship_type.is_npc());
+        // Splitting antimatter damage is a player tech, NPCs do not have it
+        assert!(Ship::guardian_advanced().weapons.iter().all(|weapon| !weapon.splittable));
+    }
+}
@@ -0,0 +1,69 @@
+use bumpalo::Bump;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use crate::simulator::{simulate_n_battles, WasmFleet};
+use crate::statistics::BattleStatistics;
+
+/// Simulates n battles split across the given number of threads.
+/// Every thread simulates its share of the battles with its own bump arena and a ChaCha8 stream
+/// of the seed, selected by the index of the thread. The result is therefore reproducible
+/// for a given seed and number of threads, but differs between thread counts.
+/// A thread count of 0 uses one thread per core.
+/// The fleets are given as descriptions, since fleets can not be shared between threads
+pub fn simulate_n_battles_parallel(
+    attacker: &WasmFleet,
+    defender: &WasmFleet,
+    n: usize,
+    seed: u64,
+    threads: usize,
+) -> BattleStatistics {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .expect("Failed to build the thread pool");
+    let threads = pool.current_num_threads();
+
+    let chunks = pool.install(|| {
+        (0..threads)
+            .into_par_iter()
+            .map(|thread| {
+                // The first threads simulate one more battle if n is not divisible by the thread count
+                let battles = n / threads + usize::from(thread < n % threads);
+                let mut rng = ChaCha8Rng::seed_from_u64(seed);
+                rng.set_stream(thread as u64);
+                let bump = Bump::new();
+                let attacker = attacker.clone().into_fleet(&bump);
+                let defender = defender.clone().into_fleet(&bump);
+                simulate_n_battles(attacker, defender, &mut rng, battles, &bump)
+            })
+            .collect::<Vec<BattleStatistics>>()
+    });
+
+    let mut statistics = BattleStatistics::default();
+    for chunk in chunks.iter() {
+        statistics.merge(chunk);
+    }
+    statistics
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parallel::simulate_n_battles_parallel;
+    use crate::simulator::{Ship, ShipType, WasmFleet, Weapon};
+
+    #[test]
+    pub fn test_parallel_is_reproducible() {
+        let interceptor = Ship::new(1, 2, 0, 1, vec![Weapon::cannon(1)], ShipType::Interceptor);
+        let cruiser = Ship::new(2, 1, 1, 1, vec![Weapon::cannon(2)], ShipType::Cruiser);
+        let attacker = WasmFleet::new(vec![interceptor.clone(), interceptor, cruiser.clone()]).unwrap();
+        let defender = WasmFleet::new(vec![cruiser; 2]).unwrap();
+
+        let statistics = simulate_n_battles_parallel(&attacker, &defender, 1001, 7, 4);
+        assert_eq!(statistics.battles, 1001);
+        assert_eq!(statistics.rounds.iter().sum::<u32>(), 1001);
+        assert_eq!(statistics, simulate_n_battles_parallel(&attacker, &defender, 1001, 7, 4));
+        assert_ne!(statistics, simulate_n_battles_parallel(&attacker, &defender, 1001, 8, 4));
+        assert_eq!(simulate_n_battles_parallel(&attacker, &defender, 1001, 7, 3).battles, 1001);
+    }
+}
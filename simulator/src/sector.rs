@@ -0,0 +1,123 @@
+use bumpalo::Bump;
+use crate::dice::Dice;
+use crate::simulator::{simulate_battle_bump, BattleResult, Fleet};
+
+/// A single battle fought while resolving a sector
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectorBattle {
+    /// Index of the attacking fleet, in order of entry
+    pub attacker: usize,
+    /// Index of the defending fleet, in order of entry
+    pub defender: usize,
+    pub result: BattleResult,
+}
+
+/// The outcome of all battles in a sector
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SectorResult {
+    /// Index of the fleet holding the sector after all battles, in order of entry.
+    /// None if no fleet has ships left
+    pub holder: Option<usize>,
+    /// The battles in the order they were fought
+    pub battles: Vec<SectorBattle>,
+}
+
+/// Resolves a sector with any number of fleets.
+/// The fleets are given in order of entry, so the first fleet is the one that held the sector first.
+/// Battles are fought pairwise in reverse order of entry: the last two fleets to enter fight first,
+/// the winner then attacks the next fleet that entered before them.
+/// Damage persists between battles, so the fleets contain the surviving ships afterwards.
+/// A fleet that retreats leaves the sector and the remaining fleet continues
+pub fn simulate_sector<T: Dice + Clone>(
+    fleets: &mut [Fleet],
+    rng: &mut T,
+    bump: &Bump,
+) -> SectorResult {
+    let mut battles = Vec::new();
+    // Fleets that entered without ships do not take part
+    let entered = (0..fleets.len())
+        .rev()
+        .filter(|i| fleets[*i].has_ships_left())
+        .collect::<Vec<usize>>();
+    let mut holder = entered.first().copied();
+
+    for &defender in entered.iter().skip(1) {
+        let Some(attacker) = holder else {
+            // Every fleet so far was destroyed, the next one holds the sector without a battle
+            holder = Some(defender);
+            continue;
+        };
+        // The attacker always entered later, so it is stored after the defender
+        let (defending, attacking) = fleets.split_at_mut(attacker);
+        if attacking[0].is_at_peace_with(&defending[defender]) {
+            // Both fleets stay in the sector, the attacker continues with the next fleet
+            continue;
+        }
+        let result = simulate_battle_bump(&mut attacking[0], &mut defending[defender], rng, bump);
+        battles.push(SectorBattle {
+            attacker,
+            defender,
+            result,
+        });
+        holder = match result {
+            BattleResult::AttackerWins | BattleResult::DefenderRetreats => Some(attacker),
+            BattleResult::DefenderWins | BattleResult::AttackerRetreats => Some(defender),
+            // The defender keeps the sector if neither fleet could destroy the other
+            BattleResult::Draw => [defender, attacker].into_iter().find(|i| fleets[*i].has_ships_left()),
+        };
+    }
+
+    SectorResult {
+        holder,
+        battles,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use crate::sector::{simulate_sector, SectorBattle};
+    use crate::simulator::{BattleResult, Fleet, Ship, ShipType, Weapon};
+
+    #[test]
+    pub fn test_reverse_entry_order() {
+        let bump = bumpalo::Bump::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        let strong = Ship::new(100, 0, 0, 5, vec![Weapon::cannon(4)], ShipType::Dreadnought);
+        let armed = Ship::new(1, 1, 0, 5, vec![Weapon::cannon(2)], ShipType::Cruiser);
+        let unarmed = Ship::new(0, 0, 0, 0, vec![], ShipType::Interceptor);
+        let mut fleets = vec![
+            Fleet::new(vec![strong], &bump).unwrap(),
+            Fleet::new(vec![unarmed], &bump).unwrap(),
+            Fleet::new(Vec::new(), &bump).unwrap(),
+            Fleet::new(vec![armed], &bump).unwrap(),
+        ];
+
+        let result = simulate_sector(&mut fleets, &mut rng, &bump);
+        assert_eq!(result.holder, Some(0));
+        assert_eq!(result.battles, vec![
+            SectorBattle { attacker: 3, defender: 1, result: BattleResult::AttackerWins },
+            SectorBattle { attacker: 3, defender: 0, result: BattleResult::DefenderWins },
+        ]);
+        assert!(!fleets[1].has_ships_left());
+        assert!(!fleets[3].has_ships_left());
+    }
+
+    #[test]
+    pub fn test_draw_without_cannons() {
+        let bump = bumpalo::Bump::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        // The missiles cannot destroy a ship, afterwards neither fleet can damage the other
+        let missile_ship = Ship::new(5, 1, 0, 0, vec![Weapon::missile(1)], ShipType::Cruiser);
+        let mut fleets = vec![
+            Fleet::new(vec![missile_ship.clone()], &bump).unwrap(),
+            Fleet::new(vec![missile_ship], &bump).unwrap(),
+        ];
+
+        let result = simulate_sector(&mut fleets, &mut rng, &bump);
+        assert_eq!(result.holder, Some(0));
+        assert_eq!(result.battles, vec![SectorBattle { attacker: 1, defender: 0, result: BattleResult::Draw }]);
+        assert!(fleets.iter().all(|fleet| fleet.has_ships_left()));
+    }
+}
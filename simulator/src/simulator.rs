@@ -1,10 +1,18 @@
-use std::cmp::Ordering;
+use std::rc::Rc;
 use bumpalo::Bump;
-use rand::{Rng, RngCore};
 use itertools::Itertools;
 use bumpalo::collections::Vec as BumpVec;
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::wasm_bindgen;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::JsError;
+use crate::dice::Dice;
+use crate::error::FleetError;
+use crate::validation::validate_ships;
+use crate::species::CombatModifiers;
+use crate::events::{BattleEvent, Recorder};
+use crate::statistics::BattleStatistics;
+use crate::targeting::{BiggestThreat, NpcRule, Objective, Targeting, TargetingStrategy};
 
 #[cfg(debug_assertions)]
 macro_rules! info {
@@ -17,27 +25,126 @@ macro_rules! info {
 }
 
 #[wasm_bindgen]
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "SavedShip")]
 pub struct Ship {
     pub hull: i32,
     pub initiative: i32,
     pub shield: i32,
     pub computer: i32,
-    pub weapon_1_dmg: i32,
-    pub weapon_2_dmg: i32,
+    /// The weapon parts of the ship. Every die of every weapon is rolled separately
+    #[wasm_bindgen(getter_with_clone)]
+    pub weapons: Vec<Weapon>,
     pub ship_type: ShipType,
 }
 
+/// A ship as it is read. Fleets saved before ships had a list of weapons
+/// have two cannon and two missile slots instead, an empty slot has a damage of 0
+#[derive(Deserialize)]
+struct SavedShip {
+    hull: i32,
+    initiative: i32,
+    shield: i32,
+    computer: i32,
+    #[serde(default)]
+    weapons: Vec<Weapon>,
+    #[serde(default)]
+    weapon_1_dmg: i32,
+    #[serde(default)]
+    weapon_2_dmg: i32,
+    #[serde(default)]
+    missile_1_dmg: i32,
+    #[serde(default)]
+    missile_2_dmg: i32,
+    ship_type: ShipType,
+}
+
+impl From<SavedShip> for Ship {
+    fn from(ship: SavedShip) -> Ship {
+        let mut weapons = ship.weapons;
+        weapons.extend([ship.weapon_1_dmg, ship.weapon_2_dmg].into_iter().filter(|damage| *damage > 0).map(Weapon::cannon));
+        weapons.extend([ship.missile_1_dmg, ship.missile_2_dmg].into_iter().filter(|damage| *damage > 0).map(Weapon::missile));
+        Ship::new(ship.hull, ship.initiative, ship.shield, ship.computer, weapons, ship.ship_type)
+    }
+}
+
+/// Determines in which part of the battle a weapon is fired
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WeaponKind {
+    /// Fires in every engagement round
+    Cannon,
+    /// Fires only once, before the first cannon round
+    Missile,
+    /// Fires in every engagement round, but ignores computers and shields.
+    /// The damage is determined by the rift die faces instead of `damage`,
+    /// some faces also damage the firing ship
+    Rift,
+}
+
+/// A weapon part of a ship. Each of its dice deals `damage` on a hit
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Weapon {
+    pub kind: WeaponKind,
+    pub damage: i32,
+    pub dice: i32,
+    /// The damage of a hit may be split across several ships (e.g. antimatter cannons)
+    #[serde(default)]
+    pub splittable: bool,
+}
+
+/// Every missile part rolls two dice
+const MISSILE_DICE_PER_PART: i32 = 2;
+
+/// The faces of the rift die as (damage to the target, damage to the firing ship)
+pub(crate) const RIFT_DIE_FACES: [(i32, i32); 6] = [(0, 0), (0, 0), (1, 0), (1, 1), (2, 0), (3, 0)];
+
+#[wasm_bindgen]
+impl Weapon {
+    #[wasm_bindgen(constructor)]
+    pub fn new(kind: WeaponKind, damage: i32, dice: i32) -> Weapon {
+        Weapon {
+            kind,
+            damage,
+            dice,
+            splittable: false,
+        }
+    }
+
+    /// A cannon part rolling a single die
+    pub fn cannon(damage: i32) -> Weapon {
+        Weapon::new(WeaponKind::Cannon, damage, 1)
+    }
+
+    /// A missile part rolling two dice
+    pub fn missile(damage: i32) -> Weapon {
+        Weapon::new(WeaponKind::Missile, damage, MISSILE_DICE_PER_PART)
+    }
+
+    /// A rift cannon part rolling a single rift die
+    pub fn rift_cannon() -> Weapon {
+        Weapon::new(WeaponKind::Rift, 0, 1)
+    }
+
+    /// Allows the damage of each hit to be split across several ships
+    pub fn with_split_damage(mut self) -> Weapon {
+        self.splittable = true;
+        self
+    }
+}
+
 #[wasm_bindgen]
 impl Ship {
+    // Exposed to javascript, where the derived Clone is not available
+    #[allow(clippy::should_implement_trait)]
     pub fn clone(&self) -> Ship {
         Ship {
             hull: self.hull,
             initiative: self.initiative,
             shield: self.shield,
             computer: self.computer,
-            weapon_1_dmg: self.weapon_1_dmg,
-            weapon_2_dmg: self.weapon_2_dmg,
+            weapons: self.weapons.clone(),
             ship_type: self.ship_type,
         }
     }
@@ -46,96 +153,407 @@ impl Ship {
 #[wasm_bindgen]
 impl Ship {
     #[wasm_bindgen(constructor)]
-    pub fn new(hull: i32, initiative: i32, shield: i32, computer: i32, weapon_1_dmg: i32, weapon_2_dmg: i32, ship_type: ShipType) -> Ship {
+    pub fn new(hull: i32, initiative: i32, shield: i32, computer: i32, weapons: Vec<Weapon>, ship_type: ShipType) -> Ship {
         Ship {
             hull,
             initiative,
             shield,
             computer,
-            weapon_1_dmg,
-            weapon_2_dmg,
+            weapons,
             ship_type,
         }
     }
 }
 
 #[wasm_bindgen]
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ShipType {
     Interceptor,
     Cruiser,
     Dreadnought,
     Starbase,
+    /// Ancient ship, see [`Ship::ancient`]
+    Ancient,
+    /// Guardian of a sector, see [`Ship::guardian`]
+    Guardian,
+    /// The Galactic Center Defense System, see [`Ship::gcds`]
+    Gcds,
+}
+
+impl ShipType {
+    /// Ships that are not controlled by a player
+    pub fn is_npc(&self) -> bool {
+        matches!(self, ShipType::Ancient | ShipType::Guardian | ShipType::Gcds)
+    }
+
+    /// The materials needed to build a ship, NPC ships can not be built
+    pub fn build_cost(&self) -> Option<u32> {
+        match self {
+            ShipType::Interceptor => Some(3),
+            ShipType::Cruiser => Some(5),
+            ShipType::Dreadnought => Some(8),
+            ShipType::Starbase => Some(3),
+            ShipType::Ancient | ShipType::Guardian | ShipType::Gcds => None,
+        }
+    }
+
+    /// Orders the ship types by size, used by the NPC targeting rule.
+    /// Player ships are ordered by the number of blueprint slots
+    pub(crate) fn size(&self) -> u8 {
+        match self {
+            ShipType::Interceptor => 0,
+            ShipType::Starbase => 1,
+            ShipType::Cruiser => 2,
+            ShipType::Dreadnought => 3,
+            ShipType::Ancient => 4,
+            ShipType::Guardian => 5,
+            ShipType::Gcds => 6,
+        }
+    }
 }
 
 impl Ship {
-    fn get_damage_index(&self) -> f32 {
-        (self.weapon_1_dmg + self.weapon_2_dmg) as f32 * (1. + self.computer as f32 / 6.).min(1.)
+    pub(crate) fn get_damage_index(&self) -> f32 {
+        let damage: i32 = self.weapons
+            .iter()
+            .filter(|weapon| weapon.kind == WeaponKind::Cannon)
+            .map(|weapon| weapon.damage * weapon.dice)
+            .sum();
+        // Rift dice always hit, so their average damage is used
+        let rift_damage: f32 = self.weapons
+            .iter()
+            .filter(|weapon| weapon.kind == WeaponKind::Rift)
+            .map(|weapon| weapon.dice as f32 * RIFT_DIE_FACES.iter().map(|(damage, _)| *damage as f32).sum::<f32>() / 6.)
+            .sum();
+        damage as f32 * (1. + self.computer as f32 / 6.).min(1.) + rift_damage
+    }
+
+    fn has_missiles(&self) -> bool {
+        self.weapons.iter().any(|weapon| weapon.kind == WeaponKind::Missile && weapon.damage > 0)
+    }
+
+    /// Cannons and rift cannons fire in every engagement round
+    fn has_cannons(&self) -> bool {
+        self.weapons.iter().any(|weapon| match weapon.kind {
+            WeaponKind::Cannon => weapon.damage > 0,
+            // The damage of a rift die is given by its faces
+            WeaponKind::Rift => true,
+            WeaponKind::Missile => false,
+        })
     }
 }
 
 #[wasm_bindgen]
 #[derive(Debug, Clone,  Serialize, Deserialize)]
+#[serde(try_from = "UncheckedFleet")]
 // #[wasm_bindgen(js_name = Fleet)]
 pub struct WasmFleet{
     ships: Vec<Ship>,
+    #[serde(default)]
+    retreat: RetreatPolicy,
+    /// Overrides the targeting that is chosen based on the ship types
+    #[serde(default)]
+    targeting: Option<Targeting>,
+    #[serde(default)]
+    objective: Objective,
+    #[serde(default)]
+    modifiers: CombatModifiers,
+}
+
+/// A fleet as it is read, before the ship stats are validated
+#[derive(Deserialize)]
+struct UncheckedFleet {
+    ships: Vec<Ship>,
+    #[serde(default)]
+    retreat: RetreatPolicy,
+    #[serde(default)]
+    targeting: Option<Targeting>,
+    #[serde(default)]
+    objective: Objective,
+    #[serde(default)]
+    modifiers: CombatModifiers,
+}
+
+impl TryFrom<UncheckedFleet> for WasmFleet {
+    type Error = FleetError;
+
+    fn try_from(fleet: UncheckedFleet) -> Result<WasmFleet, FleetError> {
+        validate_ships(&fleet.ships)?;
+        Ok(WasmFleet {
+            ships: fleet.ships,
+            retreat: fleet.retreat,
+            targeting: fleet.targeting,
+            objective: fleet.objective,
+            modifiers: fleet.modifiers,
+        })
+    }
 }
 
 impl WasmFleet {
-    pub fn into_fleet(self, bump: &Bump) -> Fleet {
-        Fleet::new(self.ships, &bump)
+    /// Creates a fleet with the default retreat policy and targeting.
+    /// Fails if any ship has stats that are out of range, see [`Ship::stat_violations`]
+    pub fn new(ships: Vec<Ship>) -> Result<WasmFleet, FleetError> {
+        validate_ships(&ships)?;
+        Ok(WasmFleet::from_valid_ships(ships))
+    }
+
+    pub(crate) fn from_valid_ships(ships: Vec<Ship>) -> WasmFleet {
+        WasmFleet {
+            ships,
+            retreat: RetreatPolicy::Never,
+            targeting: None,
+            objective: Objective::default(),
+            modifiers: CombatModifiers::default(),
+        }
+    }
+
+    pub fn into_fleet(self, bump: &Bump) -> Fleet<'_> {
+        // The ships were validated when the fleet was created
+        let fleet = Fleet::from_valid_ships(self.ships, bump)
+            .with_retreat(self.retreat)
+            .with_modifiers(self.modifiers);
+        match self.targeting {
+            Some(targeting) => fleet.with_strategy(targeting.strategy(self.objective)),
+            None => fleet,
+        }
+    }
+
+    pub fn ships(&self) -> &[Ship] {
+        &self.ships
+    }
+
+    /// The same fleet settings with other ships
+    pub(crate) fn with_ships(&self, ships: Vec<Ship>) -> Result<WasmFleet, FleetError> {
+        validate_ships(&ships)?;
+        Ok(WasmFleet { ships, ..self.clone() })
+    }
+
+    pub fn to_json(&self) -> Result<String, FleetError> {
+        serde_json::to_string(&self).map_err(|error| FleetError::Serialize(error.to_string()))
+    }
+
+    /// Reads a fleet from JSON. The error names the field that could not be read, e.g. `ships[1].hull`
+    pub fn from_json(json: &str) -> Result<WasmFleet, FleetError> {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        let fleet: UncheckedFleet = serde_path_to_error::deserialize(&mut deserializer).map_err(FleetError::from_json_error)?;
+        // Trailing characters after the fleet
+        deserializer.end().map_err(|error| FleetError::InvalidJson { path: String::new(), message: error.to_string() })?;
+        WasmFleet::try_from(fleet)
+    }
+
+    pub fn set_retreat(&mut self, retreat: RetreatPolicy) {
+        self.retreat = retreat;
+    }
+
+    pub fn set_objective(&mut self, objective: Objective) {
+        self.objective = objective;
+    }
+}
+
+#[wasm_bindgen]
+impl WasmFleet {
+    pub fn set_targeting(&mut self, targeting: Targeting) {
+        self.targeting = Some(targeting);
+    }
+
+    /// Uses the combat rules of a species, see [`Species::combat_modifiers`](crate::species::Species::combat_modifiers)
+    pub fn set_modifiers(&mut self, modifiers: CombatModifiers) {
+        self.modifiers = modifiers;
+    }
+
+    /// Retreat once the given number of engagement rounds has been fought
+    pub fn retreat_after_round(&mut self, rounds: u32) {
+        self.retreat = RetreatPolicy::AfterRound(rounds);
+    }
+
+    /// Retreat once fewer than the given number of ships are left
+    pub fn retreat_below_ships(&mut self, ships: u32) {
+        self.retreat = RetreatPolicy::BelowShips(ships);
+    }
+
+    pub fn never_retreat(&mut self) {
+        self.retreat = RetreatPolicy::Never;
     }
 }
 
+#[cfg(target_arch = "wasm32")]
 #[wasm_bindgen]
 impl WasmFleet {
+    /// Fails if any ship has stats that are out of range
     #[wasm_bindgen(constructor)]
-    pub fn new(ships: Vec<Ship>) -> WasmFleet {
-        // Sort ships by initiative at creation time.
-        WasmFleet {
-            ships
-        }
+    pub fn new_js(ships: Vec<Ship>) -> Result<WasmFleet, JsError> {
+        Ok(WasmFleet::new(ships)?)
     }
 
-    pub fn to_json(&self) -> String {
-        serde_json::to_string(&self).unwrap()
+    #[wasm_bindgen(js_name = to_json)]
+    pub fn to_json_js(&self) -> Result<String, JsError> {
+        Ok(self.to_json()?)
     }
 
-    pub fn from_json(json: &str) -> WasmFleet {
-        serde_json::from_str(json).unwrap()
+    /// Reads a fleet from JSON. The error names the field that could not be read, e.g. `ships[1].hull`
+    #[wasm_bindgen(js_name = from_json)]
+    pub fn from_json_js(json: &str) -> Result<WasmFleet, JsError> {
+        Ok(WasmFleet::from_json(json)?)
     }
 }
 
+/// Decides when a fleet retreats from a battle.
+/// A retreating fleet does not attack for one engagement round and leaves the battle
+/// at the end of that round with all ships that survived it.
+/// Starbases can not move, so a fleet with starbases left never retreats
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RetreatPolicy {
+    #[default]
+    Never,
+    /// Start to retreat once the given number of engagement rounds has been fought
+    AfterRound(u32),
+    /// Start to retreat once fewer than the given number of ships are left
+    BelowShips(u32),
+}
+
 /// A fleet is a collection of ships
 /// The ships are sorted by initiative
+/// The ship stats are shared between clones of a fleet, only the hull of each ship
+/// changes during a battle and is stored separately
 #[derive(Debug, Clone)]
 pub struct Fleet<'a> {
-    ships: BumpVec<'a, Ship>,
+    pub(crate) ships: Rc<[Ship]>,
+    /// The remaining hull of each ship, in the same order as `ships`.
+    /// Ships that are destroyed have a hull of < 0
+    pub(crate) hull: BumpVec<'a, i32>,
+    pub(crate) retreat: RetreatPolicy,
+    /// The fleet is retreating during the current engagement round and does not attack
+    pub(crate) retreating: bool,
+    /// How the hits of this fleet are assigned to the opposing fleet
+    pub(crate) targeting: Rc<dyn TargetingStrategy>,
+    pub(crate) modifiers: CombatModifiers,
+    /// Collects the events of a recorded battle, see [`record_battle`](crate::events::record_battle)
+    pub(crate) recorder: Option<Recorder>,
 }
 
 impl<'a> Fleet<'a> {
-    pub fn new<T: IntoIterator<Item=Ship>>(ships: T, bump: &'a Bump) -> Fleet<'a> {
+    /// Fails if any ship has stats that are out of range, see [`Ship::stat_violations`]
+    pub fn new<T: IntoIterator<Item=Ship>>(ships: T, bump: &'a Bump) -> Result<Fleet<'a>, FleetError> {
+        let ships = ships.into_iter().collect::<Vec<Ship>>();
+        validate_ships(&ships)?;
+        Ok(Fleet::from_valid_ships(ships, bump))
+    }
+
+    pub(crate) fn from_valid_ships(mut ships: Vec<Ship>, bump: &'a Bump) -> Fleet<'a> {
         // Sort ships by initiative at creation time.
-        let mut ships = BumpVec::from_iter_in(ships, bump);
         ships.sort_by(|a, b| a.initiative.cmp(&b.initiative).reverse());
+        let hull = BumpVec::from_iter_in(ships.iter().map(|ship| ship.hull), bump);
+        // NPC fleets follow the NPC targeting rule
+        let targeting: Rc<dyn TargetingStrategy> = if !ships.is_empty() && ships.iter().all(|ship| ship.ship_type.is_npc()) {
+            Rc::new(NpcRule)
+        } else {
+            Rc::new(BiggestThreat)
+        };
         Fleet {
-            ships
+            ships: ships.into(),
+            hull,
+            retreat: RetreatPolicy::Never,
+            retreating: false,
+            targeting,
+            modifiers: CombatModifiers::default(),
+            recorder: None,
         }
     }
 
-    pub fn clone(&self) -> Fleet {
-        Fleet {
-            ships: self.ships.clone()
+    /// Uses one of the built-in targeting strategies.
+    /// The optimal targeting maximizes the default objective, see [`Fleet::with_strategy`] for others
+    pub fn with_targeting(self, targeting: Targeting) -> Fleet<'a> {
+        self.with_strategy(targeting.strategy(Objective::default()))
+    }
+
+    /// Uses a custom targeting strategy to assign the hits of this fleet
+    pub fn with_strategy(mut self, strategy: Rc<dyn TargetingStrategy>) -> Fleet<'a> {
+        self.targeting = strategy;
+        self
+    }
+
+    pub fn with_retreat(mut self, retreat: RetreatPolicy) -> Fleet<'a> {
+        self.retreat = retreat;
+        self
+    }
+
+    /// Fights with the combat rules of a species
+    pub fn with_modifiers(mut self, modifiers: CombatModifiers) -> Fleet<'a> {
+        self.modifiers = modifiers;
+        self
+    }
+
+    /// Adds an event to the log of a recorded battle. The event is only created while recording
+    #[inline]
+    pub(crate) fn record(&self, event: impl FnOnce(Side) -> BattleEvent) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(event(recorder.side));
         }
     }
 
+    /// Records that a roll of the opposing fleet was assigned to a ship, before the ship loses hull
+    #[inline]
+    pub(crate) fn record_assigned(&self, roll: usize, ship: usize, damage: u32) {
+        self.record(|side| BattleEvent::Assigned { side, roll, target: ship, damage: damage as i32 });
+    }
+
+    /// Records that a ship lost hull, after its hull was changed
+    #[inline]
+    pub(crate) fn record_damage(&self, ship: usize, damage: i32, self_inflicted: bool) {
+        if self.recorder.is_none() || damage <= 0 {
+            return;
+        }
+        let hull = self.hull[ship];
+        self.record(|side| BattleEvent::Damage { side, ship, damage, hull, self_inflicted });
+        if hull < 0 {
+            self.record(|side| BattleEvent::Destroyed { side, ship });
+        }
+    }
+
+    /// The fleets do not fight each other, e.g. the Descendants of Draco and Ancients
+    pub(crate) fn is_at_peace_with(&self, other: &Fleet) -> bool {
+        let only_ancients = |fleet: &Fleet| !fleet.ships.is_empty() && fleet.ships.iter().all(|ship| ship.ship_type == ShipType::Ancient);
+        (self.modifiers.peaceful_ancients && only_ancients(other)) || (other.modifiers.peaceful_ancients && only_ancients(self))
+    }
+
+    /// Checks the retreat policy at the start of an engagement round
+    pub(crate) fn wants_to_retreat(&self, rounds_fought: u32) -> bool {
+        let can_retreat = self.ships
+            .iter()
+            .zip(self.hull.iter())
+            .all(|(ship, hull)| *hull < 0 || !matches!(ship.ship_type, ShipType::Starbase));
+        can_retreat && match self.retreat {
+            RetreatPolicy::Never => false,
+            RetreatPolicy::AfterRound(rounds) => rounds_fought >= rounds,
+            RetreatPolicy::BelowShips(ships) => self.num_ships() < ships as usize,
+        }
+    }
 
     pub fn has_ships_left(&self) -> bool {
-        self.ships.iter().any(|ship| ship.hull >= 0)
+        self.hull.iter().any(|hull| *hull >= 0)
     }
 
     pub fn num_ships(&self) -> usize {
-        self.ships.iter().filter(|ship| ship.hull >= 0).count()
+        self.hull.iter().filter(|hull| **hull >= 0).count()
+    }
+
+    /// The ships of the fleet, sorted by initiative
+    pub fn ships(&self) -> &[Ship] {
+        &self.ships
+    }
+
+    /// The remaining hull of each ship, in the same order as [`Fleet::ships`].
+    /// Destroyed ships have a hull of < 0
+    pub fn remaining_hull(&self) -> &[i32] {
+        &self.hull
+    }
+
+    fn has_missiles_left(&self) -> bool {
+        self.ships.iter().zip(self.hull.iter()).any(|(ship, hull)| *hull >= 0 && ship.has_missiles())
+    }
+
+    fn has_cannons_left(&self) -> bool {
+        self.ships.iter().zip(self.hull.iter()).any(|(ship, hull)| *hull >= 0 && ship.has_cannons())
     }
     /// Returns the attack order of the ships in the fleet
     /// The attack order is determined by the initiative of the ships
@@ -147,8 +565,8 @@ impl<'a> Fleet<'a> {
         let ships = self.ships
             .iter()
             .enumerate()
-            .filter(|(_, x)| x.hull >= 0)
-            .filter(move |(_, x)| x.initiative <= min_init as i32)
+            .filter(|(i, _)| self.hull[*i] >= 0)
+            .filter(move |(_, x)| x.initiative <= min_init)
             .map(|(index, x)| InitiativeIndex {
                 index,
                 initiative: x.initiative,
@@ -162,7 +580,7 @@ impl<'a> Fleet<'a> {
         let ships = self.ships
             .iter()
             .enumerate()
-            .filter(|(_, x)| x.hull >= 0)
+            .filter(|(i, _)| self.hull[*i] >= 0)
             .map(|(index, x)| InitiativeIndex {
                 index,
                 initiative: x.initiative,
@@ -181,21 +599,29 @@ struct InitiativeIndex {
     initiative: i32,
 }
 
-struct AverageDamageIndex {
-    index: usize,
-    damage: f32,
+/// One of the two fleets of a battle
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Side {
+    Attacker,
+    Defender,
 }
 
 #[wasm_bindgen]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum BattleResult {
     AttackerWins,
     DefenderWins,
+    /// Both fleets were destroyed, the fleets do not fight each other, or neither fleet can damage the other
     Draw,
+    /// The attacker retreated with at least one ship
+    AttackerRetreats,
+    /// The defender retreated with at least one ship
+    DefenderRetreats,
 }
 
 
-pub fn simulate_battle<T: RngCore + Clone>(
+pub fn simulate_battle<T: Dice + Clone>(
     attacker: &mut Fleet,
     defender: &mut Fleet,
     rng: &mut T,
@@ -204,62 +630,141 @@ pub fn simulate_battle<T: RngCore + Clone>(
     simulate_battle_bump(attacker, defender, rng, &bump)
 }
 
-pub fn simulate_n_battles<T: RngCore + Clone>(
+/// Simulates n battles between the same fleets and collects statistics about their outcomes
+pub fn simulate_n_battles<T: Dice + Clone>(
     attacker: Fleet,
     defender: Fleet,
     rng: &mut T,
     n: usize,
     bump: &Bump
-) -> f32 {
-    let mut attacker_wins = 0;
-    let mut defender_wins = 0;
-    let mut draws = 0;
+) -> BattleStatistics {
+    let mut statistics = BattleStatistics::default();
     for _ in 0..n {
-        let result = simulate_battle_bump(&mut attacker.clone(), &mut defender.clone(), rng, &bump);
-        match result {
-            BattleResult::AttackerWins => {
-                attacker_wins += 1;
-            }
-            BattleResult::DefenderWins => {
-                defender_wins += 1;
-            }
-            BattleResult::Draw => {
-                draws += 1;
-            }
-        }
+        let mut attacker = attacker.clone();
+        let mut defender = defender.clone();
+        let (result, rounds) = simulate_battle_rounds(&mut attacker, &mut defender, rng, bump);
+        statistics.record(result, rounds, &attacker, &defender);
     }
-    defender_wins as f32 / n as f32
+    statistics
 }
-pub fn simulate_battle_bump<T: RngCore + Clone>(
+
+/// Battles simulated between two checks of the precision
+const PRECISION_BATCH_SIZE: usize = 1000;
+
+/// Simulates battles in batches until the 95% confidence interval of the defender win rate
+/// is narrower than epsilon, or until max_battles have been simulated.
+/// The number of battles that were run is reported in the statistics
+pub fn simulate_until_precision<T: Dice + Clone>(
+    attacker: Fleet,
+    defender: Fleet,
+    rng: &mut T,
+    epsilon: f64,
+    max_battles: usize,
+    bump: &Bump
+) -> BattleStatistics {
+    let mut statistics = BattleStatistics::default();
+    while (statistics.battles as usize) < max_battles
+        && (statistics.battles == 0 || statistics.defender_win_interval().width() >= epsilon)
+    {
+        let batch = PRECISION_BATCH_SIZE.min(max_battles - statistics.battles as usize);
+        statistics.merge(&simulate_n_battles(attacker.clone(), defender.clone(), rng, batch, bump));
+    }
+    statistics
+}
+
+pub fn simulate_battle_bump<T: Dice + Clone>(
     attacker: &mut Fleet,
     defender: &mut Fleet,
     rng: &mut T,
     bump: &Bump
 ) -> BattleResult {
-    // let bump = Bump::new();
-    // let mut rng = rand::thread_rng();
-    while attacker.has_ships_left() && defender.has_ships_left() {
-        simulate_round_bump(attacker, defender, rng, &bump);
+    simulate_battle_rounds(attacker, defender, rng, bump).0
+}
+
+/// Simulates a battle and also returns the number of engagement rounds fought, not counting the missile round
+pub(crate) fn simulate_battle_rounds<T: Dice + Clone>(
+    attacker: &mut Fleet,
+    defender: &mut Fleet,
+    rng: &mut T,
+    bump: &Bump
+) -> (BattleResult, u32) {
+    if attacker.is_at_peace_with(defender) {
+        return (BattleResult::Draw, 0);
     }
-    if !attacker.has_ships_left() && !defender.has_ships_left() {
-        //todo: This should not happen, since the loop should terminate once one player does not have any ships left. Implement better error handling
+    simulate_missile_round_bump(attacker, defender, rng, bump);
+    let mut rounds_fought = 0;
+    // A dice script that ran out ends the battle, the caller reports the error
+    while attacker.has_ships_left() && defender.has_ships_left() && !rng.ran_out() {
+        // If the attacker retreats, the defender keeps the sector and has no reason to retreat as well
+        attacker.retreating = attacker.wants_to_retreat(rounds_fought);
+        defender.retreating = !attacker.retreating && defender.wants_to_retreat(rounds_fought);
+        // Without cannons no ship can be damaged anymore after the missile round.
+        // This is a draw unless a fleet will still retreat
+        if !attacker.has_cannons_left()
+            && !defender.has_cannons_left()
+            && !attacker.wants_to_retreat(u32::MAX)
+            && !defender.wants_to_retreat(u32::MAX)
+        {
+            return (BattleResult::Draw, rounds_fought);
+        }
+        attacker.record(|_| BattleEvent::RoundStart { round: rounds_fought + 1 });
+        for fleet in [&*attacker, &*defender].into_iter().filter(|fleet| fleet.retreating) {
+            fleet.record(|side| BattleEvent::Retreat { side });
+        }
+
+        simulate_round_bump(attacker, defender, rng, bump);
+        rounds_fought += 1;
+
+        if attacker.retreating && attacker.has_ships_left() {
+            attacker.retreating = false;
+            return (BattleResult::AttackerRetreats, rounds_fought);
+        }
+        if defender.retreating && defender.has_ships_left() {
+            defender.retreating = false;
+            return (BattleResult::DefenderRetreats, rounds_fought);
+        }
+    }
+    let result = if !attacker.has_ships_left() && !defender.has_ships_left() {
+        // Both fleets can only be destroyed at the same time if ships damage themselves (e.g. with rift cannons)
         BattleResult::Draw
     } else if !attacker.has_ships_left() {
         BattleResult::DefenderWins
     } else {
         BattleResult::AttackerWins
-    }
+    };
+    (result, rounds_fought)
 }
 
 
-pub fn simulate_round<T: RngCore + Clone>(attacker: &mut Fleet, defender: &mut Fleet, rng: &mut T) {
+pub fn simulate_round<T: Dice + Clone>(attacker: &mut Fleet, defender: &mut Fleet, rng: &mut T) {
     let bump = Bump::new();
     simulate_round_bump(attacker, defender, rng, &bump);
 }
 
-pub fn simulate_round_bump<T: RngCore + Clone>(attacker: &mut Fleet, defender: &mut Fleet, rng: &mut T, bump: &Bump) {
+pub fn simulate_round_bump<T: Dice + Clone>(attacker: &mut Fleet, defender: &mut Fleet, rng: &mut T, bump: &Bump) {
     info!("New Simulation round: \n");
+    simulate_engagement_round(attacker, defender, rng, bump, Phase::Cannons);
+}
+
+/// Fires the missiles of both fleets once in initiative order.
+/// This happens at the start of a battle, before the first cannon round
+pub fn simulate_missile_round_bump<T: Dice + Clone>(attacker: &mut Fleet, defender: &mut Fleet, rng: &mut T, bump: &Bump) {
+    if !attacker.has_missiles_left() && !defender.has_missiles_left() {
+        return;
+    }
+    info!("Missile round: \n");
+    attacker.record(|_| BattleEvent::RoundStart { round: 0 });
+    simulate_engagement_round(attacker, defender, rng, bump, Phase::Missiles);
+}
 
+/// The weapons that are fired during an engagement round
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Phase {
+    Missiles,
+    Cannons,
+}
+
+fn simulate_engagement_round<T: Dice + Clone>(attacker: &mut Fleet, defender: &mut Fleet, rng: &mut T, bump: &Bump, phase: Phase) {
     if !attacker.has_ships_left() || !defender.has_ships_left() {
         return;
     }
@@ -283,14 +788,16 @@ pub fn simulate_round_bump<T: RngCore + Clone>(attacker: &mut Fleet, defender: &
 
             let mut attacker_order = attacker.get_attack_order_max_init(best_attack_init).peekable();
             info!("Attacker attacker with init: {:?}", attacker_order.peek());
-            let mut pool = AttackPool::new_in(&bump);
+            attacker.record(|side| BattleEvent::InitiativeStep { side, initiative: best_attack_init });
+            let mut pool = AttackPool::new_in(bump);
             while attacker_order.peek().is_some()
                 && attacker_order.peek().unwrap().initiative > best_defend_init
             {
-                pool.add_ship(
-                    attacker.ships[attacker_order.next().unwrap().index].clone(),
-                    rng,
-                );
+                let index = attacker_order.next().unwrap().index;
+                // Retreating ships lose their attack
+                if !attacker.retreating {
+                    pool.add_ship(index, &attacker.ships[index], phase, rng, attacker);
+                }
             }
             best_attack_init = match attacker_order.peek() {
                 Some(x) => x.initiative,
@@ -299,22 +806,27 @@ pub fn simulate_round_bump<T: RngCore + Clone>(attacker: &mut Fleet, defender: &
                 None => -1,
             };
 
-            pool.attack_fleet(defender, bump);
+            drop(attacker_order);
+
+            pool.attack_fleet(defender, attacker.targeting.as_ref(), bump);
+            pool.damage_own_fleet(attacker);
         } else {
             // The defender attacks first
-            let mut pool = AttackPool::new_in(&bump);
+            let mut pool = AttackPool::new_in(bump);
 
             // Peek forward to the ship with the correct initiative
             let mut defender_order = defender.get_attack_order_max_init(best_defend_init).peekable();
             info!("Defender attacker with init: {:?}", defender_order.peek());
+            defender.record(|side| BattleEvent::InitiativeStep { side, initiative: best_defend_init });
 
             while defender_order.peek().is_some()
                 && defender_order.peek().unwrap().initiative >= best_attack_init
             {
-                pool.add_ship(
-                    defender.ships[defender_order.next().unwrap().index].clone(),
-                    rng,
-                );
+                let index = defender_order.next().unwrap().index;
+                // Retreating ships lose their attack
+                if !defender.retreating {
+                    pool.add_ship(index, &defender.ships[index], phase, rng, defender);
+                }
             }
             best_defend_init = match defender_order.peek() {
                 Some(x) => x.initiative,
@@ -323,178 +835,246 @@ pub fn simulate_round_bump<T: RngCore + Clone>(attacker: &mut Fleet, defender: &
                 None => -1,
             };
 
-            pool.attack_fleet(attacker, &bump);
+            drop(defender_order);
+
+            pool.attack_fleet(attacker, defender.targeting.as_ref(), bump);
+            pool.damage_own_fleet(defender);
         }
     }
 }
 
 /// Describes the amount of attacks from ships that happen at the same time
 #[derive(Debug)]
-struct AttackPool<'a> {
+pub(crate) struct AttackPool<'a> {
     /// The attack rolls of each ship in the pool, enhanced by the ships computer stat
-    enhanced_rolls: BumpVec<'a, AttackRoll>,
+    pub(crate) enhanced_rolls: BumpVec<'a, AttackRoll>,
+    /// Damage the ships in the pool deal to themselves, as (index_in_fleet, damage)
+    pub(crate) self_damage: BumpVec<'a, (usize, i32)>,
 }
 
 #[derive(Debug)]
-struct AttackRoll {
-    damage: i32,
-    hit_dc: i32,
+pub(crate) struct AttackRoll {
+    pub(crate) damage: i32,
+    pub(crate) hit_dc: i32,
+    pub(crate) splittable: bool,
+}
+
+impl AttackRoll {
+    /// Orders by damage, splittable rolls last and then by descending hit dc, packed into one integer.
+    /// The hit dc is always within +-1_000_000
+    #[inline]
+    fn sort_key(&self) -> i64 {
+        ((self.damage as i64) << 32) | ((self.splittable as i64) << 31) | (1_000_000 - self.hit_dc) as i64
+    }
 }
 
 impl<'a> AttackPool<'a> {
     /// Takes the number of ships that generate the attacks
     /// in order to preallocate the necessary space for the attack rolls
-    fn new_in(bump: &'a Bump) -> AttackPool<'a> {
+    pub(crate) fn new_in(bump: &'a Bump) -> AttackPool<'a> {
         AttackPool {
             // Each ship has two weapons, so the number of attack rolls is twice the number of ships
-            enhanced_rolls: BumpVec::new_in(bump)
+            enhanced_rolls: BumpVec::new_in(bump),
+            self_damage: BumpVec::new_in(bump),
+        }
+    }
+
+    /// Rolls the dice of the weapons of a ship. The fleet of the ship is only used to record the rolls
+    #[inline]
+    fn add_ship<T: Dice>(&mut self, index: usize, ship: &Ship, phase: Phase, rng: &mut T, fleet: &Fleet) {
+        for weapon in ship.weapons.iter() {
+            match (phase, weapon.kind) {
+                (Phase::Cannons, WeaponKind::Rift) => {
+                    for _ in 0..weapon.dice {
+                        self.add_rift_roll(index, rng, fleet);
+                    }
+                }
+                (Phase::Cannons, WeaponKind::Cannon) | (Phase::Missiles, WeaponKind::Missile) => {
+                    // Weapons without damage do not roll, they could not deal damage anyway
+                    if weapon.damage <= 0 {
+                        continue;
+                    }
+                    for _ in 0..weapon.dice {
+                        self.add_roll(index, ship, weapon, rng, fleet);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Rift dice ignore computers and shields, so every damaging face hits
+    #[inline]
+    fn add_rift_roll<T: Dice>(&mut self, index: usize, rng: &mut T, fleet: &Fleet) {
+        let face = rng.roll();
+        let (damage, self_damage) = RIFT_DIE_FACES[face as usize - 1];
+        fleet.record(|side| BattleEvent::DieRolled {
+            side,
+            ship: index,
+            kind: WeaponKind::Rift,
+            face,
+            computer: 0,
+            damage,
+        });
+        // Rolls without damage are kept so that the rolls are numbered like the dice in the battle log
+        self.enhanced_rolls.push(AttackRoll {
+            damage,
+            hit_dc: 1_000_000,
+            splittable: false,
+        });
+        if self_damage > 0 {
+            self.self_damage.push((index, self_damage));
+        }
+    }
+
+    /// Applies the damage the ships in the pool dealt to themselves to their own fleet
+    pub(crate) fn damage_own_fleet(&self, fleet: &mut Fleet) {
+        for &(index, damage) in self.self_damage.iter() {
+            if fleet.hull[index] >= 0 {
+                fleet.hull[index] -= damage;
+                info!("Ship {:?} damaged itself with {} damage", index, damage);
+                fleet.record_damage(index, damage, true);
+            }
         }
     }
 
     #[inline]
-    fn add_ship<T: RngCore>(&mut self, ship: Ship, rng: &mut T) {
+    fn add_roll<T: Dice>(&mut self, index: usize, ship: &Ship, weapon: &Weapon, rng: &mut T, fleet: &Fleet) {
         // let roll = rng.gen_range(1..=6) + ship.computer;
-        let roll = rng.gen_range(1..=6);
+        let roll = rng.roll();
+        fleet.record(|side| BattleEvent::DieRolled {
+            side,
+            ship: index,
+            kind: weapon.kind,
+            face: roll,
+            computer: ship.computer,
+            damage: weapon.damage,
+        });
         let roll = match roll {
             6 => 1_000_000,
             1 => -1_000_000,
             _ => roll + ship.computer,
         };
         self.enhanced_rolls.push(AttackRoll {
-            damage: ship.weapon_1_dmg,
+            damage: weapon.damage,
             hit_dc: roll,
+            splittable: weapon.splittable,
         });
-        if ship.weapon_2_dmg > 0 {
-            let roll = rng.gen_range(1..=6);
-            let roll = match roll {
-                6 => 1_000_000,
-                1 => -1_000_000,
-                _ => roll + ship.computer,
-            };
-            self.enhanced_rolls.push(AttackRoll {
-                damage: ship.weapon_2_dmg,
-                hit_dc: roll,
-            });
-        }
-    }
-
-    fn compare_ship_damage(a: &Ship, b: &Ship) -> Ordering {
-            let c = a.get_damage_index()
-                .partial_cmp(&b.get_damage_index())
-                // This is well-defined because the damage index is always between 0 and 1
-                .unwrap();
-            match c {
-                Ordering::Equal => {
-                    a.hull.cmp(&b.hull)
-                }
-                _ => c.reverse()
-            }
     }
 
-    fn attack_fleet(&self, fleet: &mut Fleet, bump: &Bump) {
+    pub(crate) fn attack_fleet(&self, fleet: &mut Fleet, targeting: &dyn TargetingStrategy, bump: &Bump) {
         // info!("Attacking fleet: {:?}", opposing_fleet);
 
-        let mut hit_graph = HitGraph::new(self.enhanced_rolls.len(), fleet.ships.len(), &bump);
+        // The order in which the dice were rolled must not influence the assignment.
+        // Among rolls with the same damage, the ones that hit the fewest ships come last and are used first.
+        // The edges keep the index of the roll, which is the order of the dice in the battle log
+        let mut order = BumpVec::from_iter_in(0..self.enhanced_rolls.len(), bump);
+        order.sort_unstable_by_key(|i| self.enhanced_rolls[*i].sort_key());
+
+        let mut hit_graph = HitGraph::new(fleet.ships.len(), bump);
 
         // Build hit graph
-        for i in 0..self.enhanced_rolls.len() {
+        for i in order {
+            let roll = &self.enhanced_rolls[i];
+            if roll.damage <= 0 {
+                continue;
+            }
             fleet.ships
                 .iter()
                 .enumerate()
                 // The attack hits if the roll is greater than 6 (shield and computer values ignored)
-                .filter(|(_, ship)| self.enhanced_rolls[i].hit_dc >= ship.shield + 6)
-                .filter(|(_, ship)| ship.hull >= 0)
-                .map(|(i, _)| i)
+                .filter(|(_, ship)| roll.hit_dc >= ship.shield + 6)
+                .filter(|(j, _)| fleet.hull[*j] >= 0)
+                .map(|(j, _)| j)
                 .for_each(|j| {
-                    hit_graph.add_edge(i, j, self.enhanced_rolls[i].damage as u32);
+                    hit_graph.add_edge(i, j, roll.damage as u32, roll.splittable);
                 });
         }
 
-        // For now just use a greedy approach. The ship with the highest damage index is destroyed first
-        while hit_graph.has_active_edges() {
-            let total_damage = hit_graph.total_possible_damage_per_ship();
-
-            // Loop invariant:
-            // IMPORTANT: The ships are sorted by damage index, so the ship with the highest damage index is first in the list
-            let targeted_ship = fleet.ships
-                .iter()
-                .enumerate()
-                // The ship is still alive. Ships that are destroyed have a hull of < 0
-                // .filter(|(i, _)| ships[*i].hull >= 0)
-                .filter(|(i, _)| total_damage[*i] > fleet.ships[*i].hull as u32)
-                .max_by(|(i, a), (j, b)| {
-                    Self::compare_ship_damage(a, b)
-                })
-                .map(|(i, _)| i);
-
-            // There is a ship that can be destroyed. Since the list is sorted, the first element is the one with the highest damage index
-            if let Some(ship_index) = targeted_ship {
-                // Need one more damage as the hull value to destroy the ship
-                hit_graph.deactivate_all_rolls_attacking_max_dmg(
-                    ship_index,
-                    fleet.ships[ship_index].hull as u32 + 1,
-                );
-                hit_graph.deactivate_all_edges_to_ship(ship_index);
-                fleet.ships[ship_index].hull = -1;
-                info!("Destroyed ship: {:?}", ship_index);
-                // println!("New hit graph: {:?}", hit_graph);
-            } else {
-                // No ship can be destroyed. The ship with the highest damage index is attacked
-                let ship_index = fleet.ships
-                    .iter()
-                    .enumerate()
-                    // The ship is still alive. Ships that are destroyed have a hull of < 0
-                    .filter(|(i, _)| fleet.ships[*i].hull >= 0)
-                    .filter(|(i, _)| total_damage[*i] > 0)
-                    .max_by(|(i, a), (j, b)| {
-                        Self::compare_ship_damage(a, b)
-                    })
-                    .unwrap()
-                    .0;
-                let total_damage = hit_graph.get_total_possible_damage_to_ship(ship_index);
-
-                fleet.ships[ship_index].hull -= total_damage as i32;
-                info!("Damaged ship: {:?} with {} damage", ship_index, total_damage);
-                hit_graph.deactivate_all_rolls_attacking(ship_index);
-            }
-        }
-
-        // opposing_fleet.ships = ships;
+        targeting.assign_hits(&mut hit_graph, fleet);
     }
 }
 
 /// Stores information about which ship can hit which other ship (so which damage roll succeeded)
-/// and how much damage a hit would deal
-#[derive(Debug)]
-struct HitGraph<'a> {
-    edges: BumpVec<'a, HitEdge>,
-    num_attack_rolls: usize,
+/// and how much damage a hit would deal.
+/// Targeting strategies assign the hits through [`HitGraph::destroy`], [`HitGraph::damage`]
+/// and [`HitGraph::assign_hit`], which also update the hull of the targeted fleet
+#[derive(Debug, Clone)]
+pub struct HitGraph<'a> {
+    pub(crate) edges: BumpVec<'a, HitEdge>,
     num_ships: usize,
 }
 
 impl<'a> HitGraph<'a> {
-    fn has_active_edges(&self) -> bool {
+    /// Returns true while there are hits that have not been assigned
+    pub fn has_active_edges(&self) -> bool {
         !self.edges.is_empty() && self.edges.iter().any(|edge| edge.active)
     }
-    fn add_edge(&mut self, from: usize, to: usize, damage: u32) {
+    pub(crate) fn add_edge(&mut self, from: usize, to: usize, damage: u32, splittable: bool) {
         self.edges.push(HitEdge {
             from,
             to,
             damage,
+            splittable,
             active: true,
         });
     }
 
-    fn new(num_attack_rolls: usize, num_ships: usize, bump: &'a Bump) -> HitGraph {
+    pub(crate) fn new(num_ships: usize, bump: &'a Bump) -> HitGraph<'a> {
         HitGraph {
             // Just a estimate based on nothing, still decreases total allocation time.
             edges: BumpVec::new_in(bump),
-            num_attack_rolls,
             num_ships,
         }
     }
 
-    fn total_possible_damage_per_ship(&self) -> Vec<u32> {
+    /// The hits that have not been assigned yet. A roll that hits several ships has an edge to each of them
+    pub fn hits(&self) -> impl Iterator<Item=&HitEdge> {
+        self.edges.iter().filter(|edge| edge.active)
+    }
+
+    /// Returns true if the unassigned hits can destroy the ship
+    pub fn can_destroy(&self, fleet: &Fleet, ship_index: usize) -> bool {
+        fleet.hull[ship_index] >= 0 && self.get_total_possible_damage_to_ship(ship_index) > fleet.hull[ship_index] as u32
+    }
+
+    /// Destroys the ship using as few hits as possible. The ship must be destroyable, see [`HitGraph::can_destroy`]
+    pub fn destroy(&mut self, fleet: &mut Fleet, ship_index: usize) {
+        let hull = fleet.hull[ship_index];
+        self.use_rolls_to_destroy(ship_index, hull, &mut |roll, damage| fleet.record_assigned(roll, ship_index, damage));
+        fleet.hull[ship_index] = -1;
+        info!("Destroyed ship: {:?}", ship_index);
+        fleet.record_damage(ship_index, hull + 1, false);
+    }
+
+    /// Assigns every unassigned hit that can hit the ship to it
+    pub fn damage(&mut self, fleet: &mut Fleet, ship_index: usize) {
+        let total_damage = self.get_total_possible_damage_to_ship(ship_index);
+        self.deactivate_all_rolls_attacking(ship_index, &mut |roll, damage| fleet.record_assigned(roll, ship_index, damage));
+        let hull = fleet.hull[ship_index];
+        fleet.hull[ship_index] = (hull - total_damage as i32).max(-1);
+        info!("Damaged ship: {:?} with {} damage", ship_index, total_damage);
+        fleet.record_damage(ship_index, hull - fleet.hull[ship_index], false);
+    }
+
+    /// Assigns the whole damage of a single attack roll to the ship.
+    /// The roll must be able to hit the ship
+    pub fn assign_hit(&mut self, fleet: &mut Fleet, roll: usize, ship_index: usize) {
+        let edge = self.hits()
+            .find(|edge| edge.from == roll && edge.to == ship_index)
+            .expect("The attack roll can not hit the ship");
+        let hull = fleet.hull[ship_index];
+        fleet.record_assigned(roll, ship_index, edge.damage);
+        fleet.hull[ship_index] = (hull - edge.damage as i32).max(-1);
+        fleet.record_damage(ship_index, hull - fleet.hull[ship_index], false);
+        self.deactivate_all_edges_from_attack_roll(roll);
+        if fleet.hull[ship_index] < 0 {
+            self.deactivate_all_edges_to_ship(ship_index);
+        }
+    }
+
+    /// The damage each ship can receive from the unassigned hits
+    pub fn total_possible_damage_per_ship(&self) -> Vec<u32> {
         let mut total_damage = vec![0; self.num_ships];
         for edge in self.edges.iter().filter(|edge| edge.active) {
             total_damage[edge.to] += edge.damage;
@@ -502,9 +1082,9 @@ impl<'a> HitGraph<'a> {
         total_damage
     }
 
-    fn activate_all_edges(&mut self) {
+    pub(crate) fn deactivate_all_edges(&mut self) {
         for edge in self.edges.iter_mut() {
-            edge.active = true;
+            edge.active = false;
         }
     }
 
@@ -514,8 +1094,12 @@ impl<'a> HitGraph<'a> {
         }
     }
 
-    /// Deactivates all graph edges originating from ships attacking the ship with the given index
-    fn deactivate_all_rolls_attacking(&mut self, ship_index: usize) {
+    /// Deactivates all graph edges originating from ships attacking the ship with the given index.
+    /// Every roll that can still hit the ship is passed to `assigned` with its damage
+    fn deactivate_all_rolls_attacking(&mut self, ship_index: usize, assigned: &mut impl FnMut(usize, u32)) {
+        for edge in self.edges.iter().filter(|edge| edge.active && edge.to == ship_index) {
+            assigned(edge.from, edge.damage);
+        }
         let affected_ships = self
             .edges
             .iter()
@@ -538,12 +1122,21 @@ impl<'a> HitGraph<'a> {
         }
     }
 
+    /// Uses only a part of the damage of a splittable attack roll.
+    /// The remaining damage can still be assigned to all other ships the roll hits
+    fn use_split_damage_from_attack_roll(&mut self, index: usize, damage_used: u32) {
+        for edge in self.edges.iter_mut().filter(|edge| edge.from == index) {
+            edge.damage -= damage_used;
+        }
+    }
+
     /// Deactivates all edges originating from attack rolls targeting the ship with the given index,
     /// where the damage originating from all deactivated attacking ships is only 'damage_needed'
     /// The minimum amount of damage needed is determined by a greedy algorithm that deactivates
     /// the ship with the highest damage first
     /// This may not be the optimal solution in all cases.
-    fn deactivate_all_rolls_attacking_max_dmg(&mut self, ship_index: usize, damage_needed: u32) {
+    /// The used rolls are passed to `assigned` with the damage used
+    fn deactivate_all_rolls_attacking_max_dmg(&mut self, ship_index: usize, damage_needed: u32, assigned: &mut impl FnMut(usize, u32)) {
         // println!("Deactivate edges to ship {:?}. Damage needed: {}", ship_index, damage_needed);
         let total_damage = self.get_total_possible_damage_to_ship(ship_index);
 
@@ -554,7 +1147,7 @@ impl<'a> HitGraph<'a> {
         if total_damage == damage_needed {
             // println!("Exactly enough damage to destroy ship. Deactivate all edges to {:?}", ship_index);
             // Deactivate all edges to the ship
-            self.deactivate_all_rolls_attacking(ship_index);
+            self.deactivate_all_rolls_attacking(ship_index, assigned);
             return;
         }
 
@@ -562,6 +1155,7 @@ impl<'a> HitGraph<'a> {
         // Use the edge with the highest damage first.
         // Since the fleet attacking algorithm makes sure, the highest damage ship is destroyed first,
         // this should be the best approach (since the highes damage ships generally have the highest hull value)
+        // Splittable rolls are used last, so that their unused damage can still be assigned to other ships
         let mut damage_needed: i32 = damage_needed as i32;
         info!("Needing damage: {:?}", damage_needed);
         while damage_needed > 0 {
@@ -571,17 +1165,23 @@ impl<'a> HitGraph<'a> {
                 .filter(|edge| edge.active)
                 .filter(|edge| edge.to == ship_index)
                 // .filter(|edge| edge.damage <= damage_needed)
-                .max_by(|a, b| a.damage.cmp(&b.damage));
+                .max_by(|a, b| (!a.splittable, a.damage).cmp(&(!b.splittable, b.damage)));
 
-            if edge_to_deactivate.is_some() {
-                let edge_to_deactivate = edge_to_deactivate.unwrap();
+            if let Some(edge_to_deactivate) = edge_to_deactivate {
                 // println!("edge_to_deactivate: {:?}", edge_to_deactivate);
                 // println!("Deactivate edge from {:?} to {:?} with damage {:?}", edge_to_deactivate.from, edge_to_deactivate.to, edge_to_deactivate.damage);
-                // edge_to_deactivate.active = false;
-                damage_needed -= edge_to_deactivate.damage as i32;
-
                 let from_index = edge_to_deactivate.from;
-                self.deactivate_all_edges_from_attack_roll(from_index);
+                if edge_to_deactivate.splittable && edge_to_deactivate.damage as i32 > damage_needed {
+                    // Only use the damage needed, the rest stays assignable to other ships
+                    edge_to_deactivate.active = false;
+                    assigned(from_index, damage_needed as u32);
+                    self.use_split_damage_from_attack_roll(from_index, damage_needed as u32);
+                    damage_needed = 0;
+                } else {
+                    damage_needed -= edge_to_deactivate.damage as i32;
+                    assigned(from_index, edge_to_deactivate.damage);
+                    self.deactivate_all_edges_from_attack_roll(from_index);
+                }
 
                 // break;
             } else {
@@ -592,7 +1192,41 @@ impl<'a> HitGraph<'a> {
         info!("Used damage: {:?}", damage_needed);
     }
 
-    fn get_total_possible_damage_to_ship(&self, ship_index: usize) -> u32 {
+    /// Uses the attack rolls needed to destroy the ship with the given remaining hull
+    pub(crate) fn destroy_ship(&mut self, ship_index: usize, hull: i32) {
+        self.use_rolls_to_destroy(ship_index, hull, &mut |_, _| {});
+    }
+
+    /// Like [`HitGraph::destroy_ship`], the used rolls are passed to `assigned` with the damage used
+    fn use_rolls_to_destroy(&mut self, ship_index: usize, hull: i32, assigned: &mut impl FnMut(usize, u32)) {
+        // Need one more damage as the hull value to destroy the ship
+        self.deactivate_all_rolls_attacking_max_dmg(ship_index, hull as u32 + 1, assigned);
+        self.deactivate_all_edges_to_ship(ship_index);
+    }
+
+    /// Destroys the ships with the lowest hull first until no more ships can be destroyed.
+    /// Returns the number of destroyed ships
+    pub(crate) fn count_cheapest_first_kills(&mut self, hull: &mut [i32]) -> usize {
+        let mut destroyed = 0;
+        loop {
+            let total_damage = self.total_possible_damage_per_ship();
+            let cheapest = (0..hull.len())
+                .filter(|i| hull[*i] >= 0)
+                .filter(|i| total_damage[*i] > hull[*i] as u32)
+                .min_by_key(|i| hull[*i]);
+            match cheapest {
+                Some(ship_index) => {
+                    self.destroy_ship(ship_index, hull[ship_index]);
+                    hull[ship_index] = -1;
+                    destroyed += 1;
+                }
+                None => return destroyed,
+            }
+        }
+    }
+
+    /// The damage the ship can receive from the unassigned hits
+    pub fn get_total_possible_damage_to_ship(&self, ship_index: usize) -> u32 {
         self.edges
             .iter()
             .filter(|edge| edge.active)
@@ -602,15 +1236,20 @@ impl<'a> HitGraph<'a> {
     }
 }
 
+/// A hit of an attack roll on a ship
 #[derive(Clone, Debug)]
-struct HitEdge {
-    from: usize,
-    to: usize,
-    damage: u32,
+pub struct HitEdge {
+    /// Index of the attack roll
+    pub from: usize,
+    /// Index of the ship that is hit
+    pub to: usize,
+    pub damage: u32,
+    /// The damage of the attack roll can be split between several ships
+    pub splittable: bool,
     /// An edge can be deactivated if the ship that would be hit is already destroyed.
     /// Instead of removing the edge from the graph, the active flag is set to false
     /// in order to avoid changing the graph structure
-    active: bool,
+    pub(crate) active: bool,
 }
 
 #[cfg(test)]
@@ -618,7 +1257,8 @@ mod tests {
     use rand::prelude::StdRng;
     use rand::SeedableRng;
     use crate::init_log;
-    use crate::simulator::{simulate_battle, simulate_round, BattleResult, Fleet, Ship, ShipType};
+    use crate::targeting::{BiggestThreat, NpcRule, TargetingStrategy};
+    use crate::simulator::{simulate_battle, simulate_missile_round_bump, simulate_round, simulate_until_precision, AttackPool, AttackRoll, BattleResult, Fleet, RetreatPolicy, Ship, ShipType, WasmFleet, Weapon, WeaponKind};
 
     #[test]
     pub fn test_fleet_attack() {
@@ -628,19 +1268,17 @@ mod tests {
                 initiative: 10,
                 shield: 0,
                 computer: 5,
-                weapon_1_dmg: 2,
-                weapon_2_dmg: 0,
+                weapons: vec![Weapon::cannon(2)],
                 ship_type: ShipType::Interceptor,
-            }],&bump);
+            }],&bump).unwrap();
         let mut defender = Fleet::new ( vec![Ship {
                 hull: 0,
                 initiative: 0,
                 shield: 0,
                 computer: 5,
-                weapon_1_dmg: 2,
-                weapon_2_dmg: 0,
+                weapons: vec![Weapon::cannon(2)],
                 ship_type: ShipType::Interceptor,
-            }],&bump);
+            }],&bump).unwrap();
         // Create a SEEDED RNG
 
         let mut seeded_rng = rand::rngs::StdRng::seed_from_u64(0);
@@ -666,8 +1304,7 @@ mod tests {
                     initiative: 1,
                     shield: 0,
                     computer: 1,
-                    weapon_1_dmg: 2,
-                    weapon_2_dmg: 0,
+                    weapons: vec![Weapon::cannon(2)],
                     ship_type: ShipType::Interceptor,
                 },
                 Ship {
@@ -675,11 +1312,10 @@ mod tests {
                     initiative: 1,
                     shield: 0,
                     computer: 1,
-                    weapon_1_dmg: 2,
-                    weapon_2_dmg: 0,
+                    weapons: vec![Weapon::cannon(2)],
                     ship_type: ShipType::Interceptor,
                 },
-            ],&bump);
+            ],&bump).unwrap();
 
         let defender_fleet = Fleet::new ( vec![
                 Ship {
@@ -687,13 +1323,12 @@ mod tests {
                     initiative: 1,
                     shield: 0,
                     computer: 0,
-                    weapon_1_dmg: 2,
-                    weapon_2_dmg: 0,
+                    weapons: vec![Weapon::cannon(2)],
                     ship_type: ShipType::Interceptor,
                 },
-            ],&bump);
+            ],&bump).unwrap();
         let mut defender_wins = 0;
-        for i in 0..10 {
+        for _ in 0..10 {
             // println!("Simulation {}", i);
             let result = simulate_battle(
                 &mut attacker_fleet.clone(),
@@ -716,8 +1351,7 @@ mod tests {
             initiative: 0,
             shield: 1,
             computer: 1,
-            weapon_1_dmg: 2,
-            weapon_2_dmg: 0,
+            weapons: vec![Weapon::cannon(2)],
             ship_type: ShipType::Interceptor,
         };
         let ship_proto_def = Ship {
@@ -725,17 +1359,16 @@ mod tests {
             initiative: 0,
             shield: 1,
             computer: 1,
-            weapon_1_dmg: 2,
-            weapon_2_dmg: 0,
+            weapons: vec![Weapon::cannon(2)],
             ship_type: ShipType::Interceptor,
         };
         let bump = bumpalo::Bump::new();
-        let attacker_fleet = Fleet::new ( vec![ship_proto.clone(); 5], &bump );
-        let defender_fleet = Fleet::new ( vec![ship_proto_def.clone(); 5], &bump);
+        let attacker_fleet = Fleet::new ( vec![ship_proto.clone(); 5], &bump ).unwrap();
+        let defender_fleet = Fleet::new ( vec![ship_proto_def.clone(); 5], &bump).unwrap();
 
         let mut defender_wins = 0;
         let n = 1;
-        for i in 0..n {
+        for _ in 0..n {
             // println!("Simulation {}", i);
             let result = simulate_battle(
                 &mut attacker_fleet.clone(),
@@ -748,4 +1381,225 @@ mod tests {
         }
         info!("Result: {:?}", (defender_wins as f32) / (n as f32));
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_missiles_fire_once_before_cannons() {
+        let bump = bumpalo::Bump::new();
+        let missile_ship = Ship::new(0, 0, 0, 5, vec![Weapon::missile(2)], ShipType::Interceptor);
+        let target = Ship::new(10, 0, 0, 0, vec![], ShipType::Dreadnought);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..100 {
+            let mut attacker = Fleet::new(vec![missile_ship.clone()], &bump).unwrap();
+            let mut defender = Fleet::new(vec![target.clone()], &bump).unwrap();
+            simulate_missile_round_bump(&mut attacker, &mut defender, &mut rng, &bump);
+            // Two dice with two damage each
+            let damage = 10 - defender.hull[0];
+            assert!([0, 2, 4].contains(&damage));
+
+            // Cannon rounds do not fire the missiles again
+            simulate_round(&mut attacker, &mut defender, &mut rng);
+            assert_eq!(10 - defender.hull[0], damage);
+        }
+    }
+
+    #[test]
+    pub fn test_missile_round_without_missiles() {
+        let bump = bumpalo::Bump::new();
+        let ship = Ship::new(0, 0, 0, 5, vec![Weapon::cannon(2), Weapon::cannon(2)], ShipType::Interceptor);
+        let mut attacker = Fleet::new(vec![ship.clone()], &bump).unwrap();
+        let mut defender = Fleet::new(vec![ship.clone()], &bump).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        simulate_missile_round_bump(&mut attacker, &mut defender, &mut rng, &bump);
+        assert_eq!(attacker.hull[0], 0);
+        assert_eq!(defender.hull[0], 0);
+    }
+
+    #[test]
+    pub fn test_every_die_is_rolled() {
+        let bump = bumpalo::Bump::new();
+        // Three ion cannons and a plasma cannon that always hit unless a one is rolled
+        let attacker_ship = Ship::new(0, 1, 0, 5, vec![Weapon::new(WeaponKind::Cannon, 1, 3), Weapon::cannon(2)], ShipType::Cruiser);
+        let target = Ship::new(100, 0, 0, 0, vec![], ShipType::Dreadnought);
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut max_damage = 0;
+        for _ in 0..100 {
+            let mut attacker = Fleet::new(vec![attacker_ship.clone()], &bump).unwrap();
+            let mut defender = Fleet::new(vec![target.clone()], &bump).unwrap();
+            simulate_round(&mut attacker, &mut defender, &mut rng);
+            let damage = 100 - defender.hull[0];
+            assert!((0..=5).contains(&damage));
+            max_damage = max_damage.max(damage);
+        }
+        assert_eq!(max_damage, 5);
+    }
+
+    #[test]
+    pub fn test_battle_without_cannons_is_a_draw() {
+        let bump = bumpalo::Bump::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        let missile_ship = Ship::new(1, 2, 0, 0, vec![Weapon::missile(1)], ShipType::Interceptor);
+        let starbase = Ship::new(5, 1, 0, 0, vec![], ShipType::Starbase);
+        for _ in 0..20 {
+            let mut attacker = Fleet::new(vec![missile_ship.clone()], &bump).unwrap();
+            let mut defender = Fleet::new(vec![starbase.clone()], &bump).unwrap();
+            assert_eq!(simulate_battle(&mut attacker, &mut defender, &mut rng), BattleResult::Draw);
+        }
+
+        // The missiles of two missile-only fleets may destroy each other, otherwise the battle is a draw
+        for _ in 0..100 {
+            let mut attacker = Fleet::new(vec![missile_ship.clone()], &bump).unwrap();
+            let mut defender = Fleet::new(vec![missile_ship.clone()], &bump).unwrap();
+            let result = simulate_battle(&mut attacker, &mut defender, &mut rng);
+            assert_eq!(result == BattleResult::Draw, attacker.has_ships_left() == defender.has_ships_left());
+        }
+
+        // Rift cannons keep firing although their damage is 0
+        let rift_ship = Ship::new(100, 2, 0, 0, vec![Weapon::rift_cannon()], ShipType::Cruiser);
+        let mut attacker = Fleet::new(vec![rift_ship], &bump).unwrap();
+        let mut defender = Fleet::new(vec![starbase.clone()], &bump).unwrap();
+        assert_eq!(simulate_battle(&mut attacker, &mut defender, &mut rng), BattleResult::AttackerWins);
+
+        // A fleet that retreats after a number of rounds still does so
+        let mut attacker = Fleet::new(vec![missile_ship.clone()], &bump).unwrap().with_retreat(RetreatPolicy::AfterRound(2));
+        let mut defender = Fleet::new(vec![Ship::new(5, 1, 0, 0, vec![], ShipType::Dreadnought)], &bump).unwrap();
+        assert_eq!(simulate_battle(&mut attacker, &mut defender, &mut rng), BattleResult::AttackerRetreats);
+    }
+
+    #[test]
+    pub fn test_read_fleet_with_weapon_slots() {
+        // Fleets saved with the weapon slots of earlier versions can still be read
+        let legacy = r#"{"ships": [{"hull": 1, "initiative": 2, "shield": 0, "computer": 1, "weapon_1_dmg": 2, "weapon_2_dmg": 0, "missile_1_dmg": 0, "missile_2_dmg": 1, "ship_type": "Cruiser"}]}"#;
+        let fleet = WasmFleet::new(vec![Ship::new(1, 2, 0, 1, vec![Weapon::cannon(2), Weapon::missile(1)], ShipType::Cruiser)]).unwrap();
+        assert_eq!(WasmFleet::from_json(legacy).unwrap().to_json().unwrap(), fleet.to_json().unwrap());
+    }
+
+    #[test]
+    pub fn test_split_damage_destroys_several_ships() {
+        let bump = bumpalo::Bump::new();
+        let target = Ship::new(0, 0, 0, 0, vec![], ShipType::Interceptor);
+        for (splittable, destroyed) in [(false, 1), (true, 4)] {
+            let mut fleet = Fleet::new(vec![target.clone(); 5], &bump).unwrap();
+            let mut pool = AttackPool::new_in(&bump);
+            pool.enhanced_rolls.push(AttackRoll { damage: 4, hit_dc: 1_000_000, splittable });
+            pool.attack_fleet(&mut fleet, &BiggestThreat, &bump);
+            assert_eq!(fleet.num_ships(), 5 - destroyed);
+        }
+    }
+
+    #[test]
+    pub fn test_roll_order_does_not_change_assignment() {
+        let bump = bumpalo::Bump::new();
+        let cruiser = Ship::new(0, 0, 0, 0, vec![], ShipType::Cruiser);
+        let shielded = Ship::new(0, 0, 1, 0, vec![Weapon::cannon(2)], ShipType::Interceptor);
+        // A 5 with a computer of 1 only hits the cruiser, the 6 hits both ships.
+        // The cruiser is targeted first and must take the 5, whichever die was rolled first.
+        // Without ordering the rolls, the last one that can hit is used and the 6 is wasted on the cruiser
+        for rolls in [[6, 1_000_000], [1_000_000, 6]] {
+            let mut fleet = Fleet::new(vec![cruiser.clone(), shielded.clone()], &bump).unwrap();
+            let mut pool = AttackPool::new_in(&bump);
+            for hit_dc in rolls {
+                pool.enhanced_rolls.push(AttackRoll { damage: 1, hit_dc, splittable: false });
+            }
+            pool.attack_fleet(&mut fleet, &BiggestThreat, &bump);
+            assert_eq!(fleet.num_ships(), 0, "{:?}", rolls);
+        }
+    }
+
+    #[test]
+    pub fn test_rift_cannon_ignores_shields() {
+        let bump = bumpalo::Bump::new();
+        let rift_ship = Ship::new(100, 1, 0, 0, vec![Weapon::rift_cannon()], ShipType::Cruiser);
+        let target = Ship::new(100, 0, 10, 0, vec![], ShipType::Dreadnought);
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut attacker = Fleet::new(vec![rift_ship], &bump).unwrap();
+        let mut defender = Fleet::new(vec![target], &bump).unwrap();
+        for _ in 0..50 {
+            simulate_round(&mut attacker, &mut defender, &mut rng);
+        }
+        // Every face deals damage to the target or nothing at all, no shield can block it
+        assert!(defender.hull[0] < 100);
+        // Some faces damage the firing ship
+        assert!(attacker.hull[0] < 100);
+    }
+
+    #[test]
+    pub fn test_retreat() {
+        let bump = bumpalo::Bump::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        let ship = Ship::new(0, 2, 0, 0, vec![Weapon::cannon(1)], ShipType::Interceptor);
+        let target = Ship::new(100, 0, 0, 0, vec![], ShipType::Dreadnought);
+
+        // A retreating fleet does not attack
+        let mut attacker = Fleet::new(vec![ship.clone()], &bump).unwrap().with_retreat(RetreatPolicy::AfterRound(0));
+        let mut defender = Fleet::new(vec![target.clone()], &bump).unwrap();
+        let result = simulate_battle(&mut attacker, &mut defender, &mut rng);
+        assert_eq!(result, BattleResult::AttackerRetreats);
+        assert_eq!(defender.hull[0], 100);
+
+        // The defender retreats once it lost a ship, the attacker can destroy at most two ships per round
+        let armed_ship = Ship::new(100, 0, 0, 5, vec![Weapon::cannon(1)], ShipType::Dreadnought);
+        let mut attacker = Fleet::new(vec![armed_ship.clone(); 2], &bump).unwrap();
+        let mut defender = Fleet::new(vec![ship.clone(); 5], &bump).unwrap().with_retreat(RetreatPolicy::BelowShips(5));
+        let result = simulate_battle(&mut attacker, &mut defender, &mut rng);
+        assert_eq!(result, BattleResult::DefenderRetreats);
+        assert!(defender.num_ships() < 5);
+
+        // Starbases can not retreat
+        let starbase = Ship::new(0, 2, 0, 0, vec![Weapon::cannon(1)], ShipType::Starbase);
+        let mut attacker = Fleet::new(vec![armed_ship], &bump).unwrap();
+        let mut defender = Fleet::new(vec![starbase], &bump).unwrap().with_retreat(RetreatPolicy::AfterRound(0));
+        let result = simulate_battle(&mut attacker, &mut defender, &mut rng);
+        assert_eq!(result, BattleResult::AttackerWins);
+    }
+
+    #[test]
+    pub fn test_npc_targeting_destroys_most_ships() {
+        let bump = bumpalo::Bump::new();
+        let dreadnought = Ship::new(1, 0, 0, 0, vec![], ShipType::Dreadnought);
+        let interceptor = Ship::new(0, 0, 0, 0, vec![], ShipType::Interceptor);
+        // Two ion hits can destroy the dreadnought or both interceptors
+        let strategies: [(&dyn TargetingStrategy, usize); 2] = [(&BiggestThreat, 1), (&NpcRule, 2)];
+        for (targeting, destroyed) in strategies {
+            let mut fleet = Fleet::new(vec![dreadnought.clone(), interceptor.clone(), interceptor.clone()], &bump).unwrap();
+            let mut pool = AttackPool::new_in(&bump);
+            for _ in 0..2 {
+                pool.enhanced_rolls.push(AttackRoll { damage: 1, hit_dc: 1_000_000, splittable: false });
+            }
+            pool.attack_fleet(&mut fleet, targeting, &bump);
+            assert_eq!(fleet.num_ships(), 3 - destroyed);
+        }
+
+        // If only one ship can be destroyed, the NPC destroys the largest one
+        let cruiser = Ship::new(1, 0, 0, 0, vec![], ShipType::Cruiser);
+        let mut fleet = Fleet::new(vec![interceptor.clone(), cruiser], &bump).unwrap();
+        let mut pool = AttackPool::new_in(&bump);
+        pool.enhanced_rolls.push(AttackRoll { damage: 2, hit_dc: 1_000_000, splittable: false });
+        pool.attack_fleet(&mut fleet, &NpcRule, &bump);
+        assert!(fleet.ships.iter().zip(fleet.hull.iter()).all(|(ship, hull)| (*hull < 0) == (ship.ship_type == ShipType::Cruiser)));
+
+        assert_eq!(format!("{:?}", Fleet::new(vec![Ship::ancient()], &bump).unwrap().targeting), "NpcRule");
+    }
+
+    #[test]
+    pub fn test_simulate_until_precision() {
+        let bump = bumpalo::Bump::new();
+        let mut rng = StdRng::seed_from_u64(0);
+        let strong = Ship::new(3, 1, 0, 3, vec![Weapon::cannon(4)], ShipType::Dreadnought);
+        let weak = Ship::new(0, 0, 0, 0, vec![Weapon::cannon(1)], ShipType::Interceptor);
+        let even = Ship::new(1, 0, 0, 1, vec![Weapon::cannon(1)], ShipType::Cruiser);
+
+        // A lopsided battle reaches the precision after the first batch
+        let attacker = Fleet::new(vec![strong], &bump).unwrap();
+        let defender = Fleet::new(vec![weak], &bump).unwrap();
+        let statistics = simulate_until_precision(attacker, defender, &mut rng, 0.01, 1_000_000, &bump);
+        assert_eq!(statistics.battles, 1000);
+        assert!(statistics.defender_win_interval().width() < 0.01);
+
+        // A close battle runs until the cap
+        let attacker = Fleet::new(vec![even.clone()], &bump).unwrap();
+        let defender = Fleet::new(vec![even], &bump).unwrap();
+        let statistics = simulate_until_precision(attacker, defender, &mut rng, 0.001, 2500, &bump);
+        assert_eq!(statistics.battles, 2500);
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::blueprint::{Blueprint, Part};
+use crate::error::BlueprintError;
+use crate::simulator::{Ship, ShipType};
+
+/// The playable species of Second Dawn. The Terran factions share the standard player board
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Species {
+    #[default]
+    Terran,
+    EridaniEmpire,
+    HydranProgress,
+    /// Blueprints with one slot less, but with energy printed on them
+    Planta,
+    /// Ancients are not hostile to the Descendants of Draco
+    DescendantsOfDraco,
+    Mechanema,
+    /// Faster blueprints that start with a Gauss Shield
+    OrionHegemony,
+}
+
+/// Combat rules in which a fleet differs from the standard rules
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CombatModifiers {
+    /// The fleet and a fleet of Ancients do not fight each other
+    pub peaceful_ancients: bool,
+}
+
+impl Species {
+    pub const ALL: [Species; 7] = [
+        Species::Terran,
+        Species::EridaniEmpire,
+        Species::HydranProgress,
+        Species::Planta,
+        Species::DescendantsOfDraco,
+        Species::Mechanema,
+        Species::OrionHegemony,
+    ];
+
+    /// The number of part slots of the blueprint, NPC ships have no blueprint
+    pub fn blueprint_slots(&self, ship_type: ShipType) -> Option<usize> {
+        let slots = ship_type.blueprint_slots()?;
+        match self {
+            Species::Planta => Some(slots - 1),
+            _ => Some(slots),
+        }
+    }
+
+    /// The initiative printed on the blueprint
+    pub fn base_initiative(&self, ship_type: ShipType) -> i32 {
+        match self {
+            Species::OrionHegemony => ship_type.base_initiative() + 1,
+            _ => ship_type.base_initiative(),
+        }
+    }
+
+    /// The energy printed on the blueprint
+    pub fn base_energy(&self, ship_type: ShipType) -> i32 {
+        match self {
+            Species::EridaniEmpire => ship_type.base_energy() + 1,
+            Species::Planta => ship_type.base_energy() + 2,
+            _ => ship_type.base_energy(),
+        }
+    }
+
+    /// The parts printed in the slots of the blueprint at the start of the game
+    pub fn default_blueprint(&self, ship_type: ShipType) -> Result<Blueprint, BlueprintError> {
+        use Part::*;
+        let mut parts = match ship_type {
+            ShipType::Interceptor => vec![IonCannon, NuclearSource, NuclearDrive],
+            ShipType::Cruiser => vec![IonCannon, Hull, ElectronComputer, NuclearSource, NuclearDrive],
+            ShipType::Dreadnought => vec![IonCannon, IonCannon, Hull, Hull, ElectronComputer, NuclearSource, NuclearDrive],
+            ShipType::Starbase => vec![Hull, Hull, IonCannon, ElectronComputer],
+            _ => return Err(BlueprintError::NoBlueprint(ship_type)),
+        };
+        if *self == Species::OrionHegemony {
+            parts.push(GaussShield);
+        }
+        Blueprint::for_species(*self, ship_type, parts)
+    }
+
+    pub fn combat_modifiers(&self) -> CombatModifiers {
+        CombatModifiers {
+            peaceful_ancients: *self == Species::DescendantsOfDraco,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Ship {
+    /// The ship built from the default blueprint of the species, undefined for NPC ships
+    pub fn species_default(species: Species, ship_type: ShipType) -> Option<Ship> {
+        species.default_blueprint(ship_type).ok().map(|blueprint| blueprint.to_ship())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::exact::solve_battle;
+    use crate::simulator::{simulate_n_battles, Fleet, Ship, ShipType};
+    use crate::species::Species;
+
+    #[test]
+    pub fn test_species_blueprints() {
+        for species in Species::ALL {
+            for ship_type in [ShipType::Interceptor, ShipType::Cruiser, ShipType::Dreadnought, ShipType::Starbase] {
+                let blueprint = species.default_blueprint(ship_type).unwrap();
+                assert!(blueprint.energy_balance() >= 0, "{:?} {:?}", species, ship_type);
+            }
+            assert!(Ship::species_default(species, ShipType::Guardian).is_none());
+        }
+
+        let terran = Ship::species_default(Species::Terran, ShipType::Cruiser).unwrap();
+        let orion = Ship::species_default(Species::OrionHegemony, ShipType::Cruiser).unwrap();
+        assert_eq!((orion.hull, orion.initiative, orion.shield), (terran.hull, terran.initiative + 1, 1));
+        assert_eq!(Species::OrionHegemony.default_blueprint(ShipType::Cruiser).unwrap().free_slots(), 0);
+
+        let planta = Species::Planta.default_blueprint(ShipType::Dreadnought).unwrap();
+        assert_eq!((planta.free_slots(), planta.energy_balance()), (0, 2));
+        let eridani = Species::EridaniEmpire.default_blueprint(ShipType::Interceptor).unwrap();
+        assert_eq!(eridani.energy_balance(), 2);
+    }
+
+    #[test]
+    pub fn test_peaceful_ancients() {
+        let bump = Bump::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let draco = Species::DescendantsOfDraco;
+        let cruiser = Ship::species_default(draco, ShipType::Cruiser).unwrap();
+        let attacker = Fleet::new(vec![cruiser.clone(); 2], &bump).unwrap().with_modifiers(draco.combat_modifiers());
+        let ancients = Fleet::new(vec![Ship::ancient(); 2], &bump).unwrap();
+
+        let statistics = simulate_n_battles(attacker.clone(), ancients.clone(), &mut rng, 100, &bump);
+        assert_eq!((statistics.draws, statistics.rounds[0]), (100, 100));
+        assert_eq!(statistics.expected_defender_survivors().ancient, 2.);
+        assert_eq!(solve_battle(&attacker, &ancients).draw, 1.);
+
+        // The Guardian is not an Ancient and fights as usual
+        let guardian = Fleet::new(vec![Ship::guardian()], &bump).unwrap();
+        let statistics = simulate_n_battles(attacker, guardian, &mut rng, 100, &bump);
+        assert_eq!(statistics.draws, 0);
+    }
+}
@@ -0,0 +1,305 @@
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::simulator::{BattleResult, Fleet, ShipType};
+
+/// Number of ship types, used to count ships per type
+const SHIP_TYPES: usize = 7;
+
+/// The z value of a 95% confidence interval
+const Z_95: f64 = 1.959964;
+
+/// A value for each ship type
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ShipTypeCounts {
+    pub interceptor: f64,
+    pub cruiser: f64,
+    pub dreadnought: f64,
+    pub starbase: f64,
+    pub ancient: f64,
+    pub guardian: f64,
+    pub gcds: f64,
+}
+
+#[wasm_bindgen]
+impl ShipTypeCounts {
+    pub fn get(&self, ship_type: ShipType) -> f64 {
+        match ship_type {
+            ShipType::Interceptor => self.interceptor,
+            ShipType::Cruiser => self.cruiser,
+            ShipType::Dreadnought => self.dreadnought,
+            ShipType::Starbase => self.starbase,
+            ShipType::Ancient => self.ancient,
+            ShipType::Guardian => self.guardian,
+            ShipType::Gcds => self.gcds,
+        }
+    }
+}
+
+/// The ships of one side that survived a number of battles
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SurvivorHistogram {
+    battles: u32,
+    /// For each ship type, the number of battles in which the given number of ships survived
+    counts: [Vec<u32>; SHIP_TYPES],
+    /// For each ship type, the number of surviving ships with the given remaining hull, over all battles
+    hull: [Vec<u32>; SHIP_TYPES],
+}
+
+impl SurvivorHistogram {
+    fn record(&mut self, fleet: &Fleet) {
+        self.battles += 1;
+        let mut counts = [0; SHIP_TYPES];
+        for (ship, hull) in fleet.ships.iter().zip(fleet.hull.iter()) {
+            if *hull >= 0 {
+                counts[ship.ship_type as usize] += 1;
+                increment(&mut self.hull[ship.ship_type as usize], *hull as usize);
+            }
+        }
+        for (histogram, count) in self.counts.iter_mut().zip(counts) {
+            increment(histogram, count);
+        }
+    }
+}
+
+impl SurvivorHistogram {
+    fn merge(&mut self, other: &SurvivorHistogram) {
+        self.battles += other.battles;
+        for (histogram, other) in self.counts.iter_mut().chain(self.hull.iter_mut()).zip(other.counts.iter().chain(other.hull.iter())) {
+            add_histogram(histogram, other);
+        }
+    }
+}
+
+fn add_histogram(histogram: &mut Vec<u32>, other: &[u32]) {
+    if histogram.len() < other.len() {
+        histogram.resize(other.len(), 0);
+    }
+    for (count, other) in histogram.iter_mut().zip(other) {
+        *count += other;
+    }
+}
+
+fn increment(histogram: &mut Vec<u32>, index: usize) {
+    if histogram.len() <= index {
+        histogram.resize(index + 1, 0);
+    }
+    histogram[index] += 1;
+}
+
+#[wasm_bindgen]
+impl SurvivorHistogram {
+    /// The number of battles in which the given number of ships of the type survived, indexed by that number
+    pub fn survivor_counts(&self, ship_type: ShipType) -> Vec<u32> {
+        self.counts[ship_type as usize].clone()
+    }
+
+    /// The number of surviving ships of the type with the given remaining hull, indexed by the hull.
+    /// A remaining hull of 0 means that the next damage destroys the ship
+    pub fn remaining_hull(&self, ship_type: ShipType) -> Vec<u32> {
+        self.hull[ship_type as usize].clone()
+    }
+
+    /// The probability that at least the given number of ships of the type survive
+    pub fn probability_at_least(&self, ship_type: ShipType, ships: usize) -> f64 {
+        if self.battles == 0 {
+            return 0.;
+        }
+        let battles: u32 = self.counts[ship_type as usize].iter().skip(ships).sum();
+        battles as f64 / self.battles as f64
+    }
+
+    /// The mean number of ships of each type that survive a battle
+    pub fn expected(&self) -> ShipTypeCounts {
+        let mean = |ship_type: ShipType| match self.battles {
+            0 => 0.,
+            battles => self.counts[ship_type as usize]
+                .iter()
+                .enumerate()
+                .map(|(ships, count)| (ships as u64 * *count as u64) as f64)
+                .sum::<f64>() / battles as f64,
+        };
+        ShipTypeCounts {
+            interceptor: mean(ShipType::Interceptor),
+            cruiser: mean(ShipType::Cruiser),
+            dreadnought: mean(ShipType::Dreadnought),
+            starbase: mean(ShipType::Starbase),
+            ancient: mean(ShipType::Ancient),
+            guardian: mean(ShipType::Guardian),
+            gcds: mean(ShipType::Gcds),
+        }
+    }
+}
+
+/// The range that contains the true probability with a given confidence
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfidenceInterval {
+    pub low: f64,
+    pub high: f64,
+}
+
+#[wasm_bindgen]
+impl ConfidenceInterval {
+    pub fn width(&self) -> f64 {
+        self.high - self.low
+    }
+}
+
+/// The outcomes of a number of simulated battles between the same fleets
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BattleStatistics {
+    pub battles: u32,
+    pub attacker_wins: u32,
+    pub defender_wins: u32,
+    pub draws: u32,
+    pub attacker_retreats: u32,
+    pub defender_retreats: u32,
+    /// The number of battles that lasted the given number of engagement rounds.
+    /// The missile round is not counted, so index 0 counts battles decided by missiles
+    #[wasm_bindgen(getter_with_clone)]
+    pub rounds: Vec<u32>,
+    #[wasm_bindgen(getter_with_clone)]
+    pub attacker_survivors: SurvivorHistogram,
+    #[wasm_bindgen(getter_with_clone)]
+    pub defender_survivors: SurvivorHistogram,
+}
+
+impl BattleStatistics {
+    /// Adds a finished battle
+    pub(crate) fn record(&mut self, result: BattleResult, rounds: u32, attacker: &Fleet, defender: &Fleet) {
+        self.battles += 1;
+        match result {
+            BattleResult::AttackerWins => self.attacker_wins += 1,
+            BattleResult::DefenderWins => self.defender_wins += 1,
+            BattleResult::Draw => self.draws += 1,
+            BattleResult::AttackerRetreats => self.attacker_retreats += 1,
+            BattleResult::DefenderRetreats => self.defender_retreats += 1,
+        }
+        increment(&mut self.rounds, rounds as usize);
+        self.attacker_survivors.record(attacker);
+        self.defender_survivors.record(defender);
+    }
+
+    /// Adds the battles of other statistics, e.g. from another thread
+    pub fn merge(&mut self, other: &BattleStatistics) {
+        self.battles += other.battles;
+        self.attacker_wins += other.attacker_wins;
+        self.defender_wins += other.defender_wins;
+        self.draws += other.draws;
+        self.attacker_retreats += other.attacker_retreats;
+        self.defender_retreats += other.defender_retreats;
+        add_histogram(&mut self.rounds, &other.rounds);
+        self.attacker_survivors.merge(&other.attacker_survivors);
+        self.defender_survivors.merge(&other.defender_survivors);
+    }
+}
+
+#[wasm_bindgen]
+impl BattleStatistics {
+    pub fn attacker_win_rate(&self) -> f64 {
+        self.rate(self.attacker_wins)
+    }
+
+    pub fn defender_win_rate(&self) -> f64 {
+        self.rate(self.defender_wins)
+    }
+
+    pub fn draw_rate(&self) -> f64 {
+        self.rate(self.draws)
+    }
+
+    /// The 95% Wilson score interval of the defender win rate
+    pub fn defender_win_interval(&self) -> ConfidenceInterval {
+        if self.battles == 0 {
+            return ConfidenceInterval { low: 0., high: 1. };
+        }
+        let n = self.battles as f64;
+        let p = self.defender_win_rate();
+        let z2 = Z_95 * Z_95;
+        let denominator = 1. + z2 / n;
+        let center = (p + z2 / (2. * n)) / denominator;
+        let half_width = Z_95 * (p * (1. - p) / n + z2 / (4. * n * n)).sqrt() / denominator;
+        ConfidenceInterval {
+            low: (center - half_width).max(0.),
+            high: (center + half_width).min(1.),
+        }
+    }
+
+    /// The mean number of attacker ships of each type that survive a battle
+    pub fn expected_attacker_survivors(&self) -> ShipTypeCounts {
+        self.attacker_survivors.expected()
+    }
+
+    /// The mean number of defender ships of each type that survive a battle
+    pub fn expected_defender_survivors(&self) -> ShipTypeCounts {
+        self.defender_survivors.expected()
+    }
+
+    fn rate(&self, count: u32) -> f64 {
+        match self.battles {
+            0 => 0.,
+            battles => count as f64 / battles as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::simulator::{simulate_n_battles, Fleet, Ship, ShipType, Weapon};
+
+    #[test]
+    pub fn test_battle_statistics() {
+        let bump = bumpalo::Bump::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let cruiser = Ship::new(1, 2, 0, 1, vec![Weapon::cannon(1)], ShipType::Cruiser);
+        let interceptor = Ship::new(0, 3, 0, 0, vec![Weapon::cannon(1)], ShipType::Interceptor);
+        let attacker = Fleet::new(vec![cruiser.clone(), interceptor.clone()], &bump).unwrap();
+        let defender = Fleet::new(vec![cruiser, interceptor], &bump).unwrap();
+
+        let statistics = simulate_n_battles(attacker, defender, &mut rng, 5_000, &bump);
+        assert_eq!(statistics.battles, 5_000);
+        assert_eq!(statistics.attacker_wins + statistics.defender_wins + statistics.draws, 5_000);
+        assert_eq!(statistics.rounds.iter().sum::<u32>(), 5_000);
+        // Without missiles, every battle lasts at least one round
+        assert_eq!(statistics.rounds[0], 0);
+
+        let interval = statistics.defender_win_interval();
+        assert!(interval.low < statistics.defender_win_rate() && statistics.defender_win_rate() < interval.high);
+        assert!(interval.width() < 0.04);
+
+        // The winner keeps at least one ship
+        let survivors = statistics.expected_attacker_survivors();
+        assert_eq!(survivors.get(ShipType::Dreadnought), 0.);
+        let ships = survivors.interceptor + survivors.cruiser;
+        assert!(ships >= statistics.attacker_win_rate() && ships <= 2. * statistics.attacker_win_rate());
+    }
+
+    #[test]
+    pub fn test_survivor_histogram() {
+        let bump = bumpalo::Bump::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let dreadnought = Ship::new(3, 1, 0, 1, vec![Weapon::cannon(2)], ShipType::Dreadnought);
+        let interceptor = Ship::new(0, 2, 0, 1, vec![Weapon::cannon(1)], ShipType::Interceptor);
+        let attacker = Fleet::new(vec![dreadnought.clone(), dreadnought], &bump).unwrap();
+        let defender = Fleet::new(vec![interceptor; 4], &bump).unwrap();
+
+        let statistics = simulate_n_battles(attacker, defender, &mut rng, 2_000, &bump);
+        let survivors = &statistics.attacker_survivors;
+        let counts = survivors.survivor_counts(ShipType::Dreadnought);
+        assert_eq!(counts.iter().sum::<u32>(), 2_000);
+        assert!(counts.len() <= 3);
+        // A dreadnought survives every battle the attacker wins
+        assert!(survivors.probability_at_least(ShipType::Dreadnought, 1) >= statistics.attacker_win_rate());
+        assert_eq!(survivors.probability_at_least(ShipType::Dreadnought, 0), 1.);
+        assert_eq!(survivors.probability_at_least(ShipType::Dreadnought, 3), 0.);
+
+        let hull = survivors.remaining_hull(ShipType::Dreadnought);
+        assert!(hull.len() <= 4);
+        assert_eq!(hull.iter().sum::<u32>(), counts.iter().enumerate().map(|(ships, count)| ships as u32 * count).sum::<u32>());
+        assert!(survivors.remaining_hull(ShipType::Cruiser).is_empty());
+    }
+}
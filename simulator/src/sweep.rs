@@ -0,0 +1,171 @@
+use bumpalo::Bump;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::error::{FleetError, StatViolation};
+use crate::simulator::{simulate_n_battles, Ship, ShipType, WasmFleet};
+pub use crate::simulator::Side;
+use crate::statistics::BattleStatistics;
+
+/// The value that is changed between the points of a sweep
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SweepParameter {
+    Hull,
+    Initiative,
+    Shield,
+    Computer,
+    /// The number of ships of the type, copies of the first ship of the type are added or removed
+    ShipCount,
+}
+
+impl SweepParameter {
+    pub const ALL: [SweepParameter; 5] = [
+        SweepParameter::Hull,
+        SweepParameter::Initiative,
+        SweepParameter::Shield,
+        SweepParameter::Computer,
+        SweepParameter::ShipCount,
+    ];
+}
+
+/// Varies one stat of all ships of a type in one fleet, or their number, from `from` to `to` inclusive
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sweep {
+    pub side: Side,
+    pub ship_type: ShipType,
+    pub parameter: SweepParameter,
+    pub from: i32,
+    pub to: i32,
+}
+
+#[wasm_bindgen]
+impl Sweep {
+    #[wasm_bindgen(constructor)]
+    pub fn new(side: Side, ship_type: ShipType, parameter: SweepParameter, from: i32, to: i32) -> Sweep {
+        Sweep {
+            side,
+            ship_type,
+            parameter,
+            from,
+            to,
+        }
+    }
+}
+
+/// The outcome of the battles at one value of a sweep
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepPoint {
+    pub value: i32,
+    #[wasm_bindgen(getter_with_clone)]
+    pub statistics: BattleStatistics,
+}
+
+impl Sweep {
+    /// The fleet with the swept value applied to the ships of the type
+    fn apply(&self, fleet: &WasmFleet, value: i32) -> Result<WasmFleet, FleetError> {
+        let ship_type = self.ship_type;
+        let index = fleet
+            .ships()
+            .iter()
+            .position(|ship| ship.ship_type == ship_type)
+            .ok_or(FleetError::MissingShipType(ship_type))?;
+        let template = fleet.ships()[index].clone();
+        let mut ships = fleet.ships().to_vec();
+        match self.parameter {
+            SweepParameter::ShipCount if value < 0 => {
+                return Err(FleetError::InvalidStats(vec![StatViolation {
+                    ship: index,
+                    field: "count".to_string(),
+                    value,
+                    min: 0,
+                    max: i32::MAX,
+                }]));
+            }
+            SweepParameter::ShipCount => {
+                ships.retain(|ship| ship.ship_type != ship_type);
+                ships.extend(std::iter::repeat_n(template, value as usize));
+            }
+            parameter => {
+                for ship in ships.iter_mut().filter(|ship| ship.ship_type == ship_type) {
+                    *field(ship, parameter) = value;
+                }
+            }
+        }
+        fleet.with_ships(ships)
+    }
+}
+
+fn field(ship: &mut Ship, parameter: SweepParameter) -> &mut i32 {
+    match parameter {
+        SweepParameter::Hull => &mut ship.hull,
+        SweepParameter::Initiative => &mut ship.initiative,
+        SweepParameter::Shield => &mut ship.shield,
+        SweepParameter::Computer => &mut ship.computer,
+        SweepParameter::ShipCount => unreachable!("the ship count is not a field of a ship"),
+    }
+}
+
+/// Simulates n battles for every value of the sweep.
+/// Every point uses a new ChaCha8 generator from the same seed, so neighbouring points are
+/// simulated with the same dice and the curve is smooth.
+/// Fails if the fleet has no ship of the swept type, or a value is not a valid stat or a negative ship count
+pub fn sweep(
+    attacker: &WasmFleet,
+    defender: &WasmFleet,
+    sweep: &Sweep,
+    n: usize,
+    seed: u64,
+) -> Result<Vec<SweepPoint>, FleetError> {
+    (sweep.from..=sweep.to)
+        .map(|value| {
+            let (attacker, defender) = match sweep.side {
+                Side::Attacker => (sweep.apply(attacker, value)?, defender.clone()),
+                Side::Defender => (attacker.clone(), sweep.apply(defender, value)?),
+            };
+            let bump = Bump::new();
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let statistics = simulate_n_battles(attacker.into_fleet(&bump), defender.into_fleet(&bump), &mut rng, n, &bump);
+            Ok(SweepPoint { value, statistics })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::FleetError;
+    use crate::simulator::{Ship, ShipType, WasmFleet, Weapon};
+    use crate::sweep::{sweep, Side, Sweep, SweepParameter};
+
+    #[test]
+    pub fn test_sweep() {
+        let cruiser = Ship::new(1, 2, 0, 1, vec![Weapon::cannon(2)], ShipType::Cruiser);
+        let attacker = WasmFleet::new(vec![cruiser.clone(); 2]).unwrap();
+        let defender = WasmFleet::new(vec![cruiser, Ship::ancient()]).unwrap();
+
+        let shields = sweep(&attacker, &defender, &Sweep::new(Side::Defender, ShipType::Cruiser, SweepParameter::Shield, 0, 3), 2_000, 0).unwrap();
+        assert_eq!(shields.iter().map(|point| point.value).collect::<Vec<i32>>(), [0, 1, 2, 3]);
+        assert!(shields.iter().all(|point| point.statistics.battles == 2_000));
+        assert!(shields.windows(2).all(|pair| pair[0].statistics.defender_wins <= pair[1].statistics.defender_wins));
+        // A 6 always hits, so a third shield does not help against a computer of 1 and the dice are the same
+        assert!(shields[0].statistics.defender_wins < shields[2].statistics.defender_wins);
+        assert_eq!(shields[2].statistics, shields[3].statistics);
+
+        let counts = sweep(&attacker, &defender, &Sweep::new(Side::Attacker, ShipType::Cruiser, SweepParameter::ShipCount, 1, 4), 2_000, 0).unwrap();
+        assert!(counts[0].statistics.expected_attacker_survivors().cruiser <= 1.);
+        assert!(counts.windows(2).all(|pair| pair[0].statistics.attacker_wins < pair[1].statistics.attacker_wins));
+        // No ships of the type is a valid point
+        let none = sweep(&attacker, &defender, &Sweep::new(Side::Attacker, ShipType::Cruiser, SweepParameter::ShipCount, 0, 0), 10, 0).unwrap();
+        assert_eq!(none[0].statistics.defender_wins, 10);
+
+        let missing = Sweep::new(Side::Attacker, ShipType::Dreadnought, SweepParameter::Hull, 0, 1);
+        assert_eq!(sweep(&attacker, &defender, &missing, 10, 0), Err(FleetError::MissingShipType(ShipType::Dreadnought)));
+        let negative = Sweep::new(Side::Defender, ShipType::Ancient, SweepParameter::Computer, -1, 1);
+        assert!(matches!(sweep(&attacker, &defender, &negative, 10, 0), Err(FleetError::InvalidStats(_))));
+        let negative = Sweep::new(Side::Defender, ShipType::Cruiser, SweepParameter::ShipCount, -1, 1);
+        let error = sweep(&attacker, &defender, &negative, 10, 0).unwrap_err();
+        assert_eq!(error.to_string(), "invalid fleet: ships[0].count is -1 but must be at least 0");
+    }
+}
@@ -0,0 +1,540 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::rc::Rc;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::wasm_bindgen;
+use crate::simulator::{Fleet, HitGraph, ShipType};
+
+/// Assigns the hits of an attack to the ships of the opposing fleet.
+/// Every fleet carries its own strategy, see [`Fleet::with_strategy`]
+pub trait TargetingStrategy: Debug {
+    /// Assigns the hits in the graph to the ships of the fleet.
+    /// Hits that are still unassigned afterwards are lost
+    fn assign_hits(&self, hit_graph: &mut HitGraph, fleet: &mut Fleet);
+}
+
+/// Selects one of the built-in targeting strategies
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Targeting {
+    /// See [`BiggestThreat`]
+    #[default]
+    DamageIndex,
+    /// See [`NpcRule`]
+    Npc,
+    /// See [`OptimalAssignment`]
+    Optimal,
+    /// See [`MostKills`]
+    MostKills,
+    /// See [`FocusStarbase`]
+    FocusStarbase,
+    /// See [`SpreadDamage`]
+    SpreadDamage,
+}
+
+impl Targeting {
+    /// Creates the strategy. The objective is only used by the optimal assignment
+    pub fn strategy(self, objective: Objective) -> Rc<dyn TargetingStrategy> {
+        match self {
+            Targeting::DamageIndex => Rc::new(BiggestThreat),
+            Targeting::Npc => Rc::new(NpcRule),
+            Targeting::Optimal => Rc::new(OptimalAssignment { objective }),
+            Targeting::MostKills => Rc::new(MostKills),
+            Targeting::FocusStarbase => Rc::new(FocusStarbase),
+            Targeting::SpreadDamage => Rc::new(SpreadDamage),
+        }
+    }
+}
+
+/// Orders ships by damage index, ships with a lower hull first in case of a draw
+fn compare_ship_damage(fleet: &Fleet, a: usize, b: usize) -> Ordering {
+    let c = fleet.ships[a].get_damage_index()
+        .partial_cmp(&fleet.ships[b].get_damage_index())
+        // This is well-defined because the damage index is never NaN
+        .unwrap();
+    match c {
+        Ordering::Equal => fleet.hull[a].cmp(&fleet.hull[b]),
+        _ => c.reverse(),
+    }
+}
+
+/// The ship is alive and the possible damage exceeds its remaining hull
+fn can_destroy(fleet: &Fleet, total_damage: &[u32], ship_index: usize) -> bool {
+    fleet.hull[ship_index] >= 0 && total_damage[ship_index] > fleet.hull[ship_index] as u32
+}
+
+/// Destroys the ship with the highest damage index first.
+/// If no ship can be destroyed, damages the ship with the highest damage index
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BiggestThreat;
+
+impl TargetingStrategy for BiggestThreat {
+    fn assign_hits(&self, hit_graph: &mut HitGraph, fleet: &mut Fleet) {
+        // Greedy approach: the ship with the highest damage index is destroyed first
+        while hit_graph.has_active_edges() {
+            let total_damage = hit_graph.total_possible_damage_per_ship();
+            let targeted_ship = (0..fleet.ships.len())
+                .filter(|i| can_destroy(fleet, &total_damage, *i))
+                .max_by(|a, b| compare_ship_damage(fleet, *a, *b));
+
+            if let Some(ship_index) = targeted_ship {
+                hit_graph.destroy(fleet, ship_index);
+            } else {
+                let ship_index = (0..fleet.ships.len())
+                    .filter(|i| fleet.hull[*i] >= 0)
+                    .filter(|i| total_damage[*i] > 0)
+                    .max_by(|a, b| compare_ship_damage(fleet, *a, *b))
+                    .unwrap();
+                hit_graph.damage(fleet, ship_index);
+            }
+        }
+    }
+}
+
+/// The NPC rule: destroys as many ships as possible, starting with the largest ship.
+/// If no ship can be destroyed, deals as much damage as possible to the largest ship
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NpcRule;
+
+impl TargetingStrategy for NpcRule {
+    fn assign_hits(&self, hit_graph: &mut HitGraph, fleet: &mut Fleet) {
+        while hit_graph.has_active_edges() {
+            let total_damage = hit_graph.total_possible_damage_per_ship();
+            // Destroy the ship that allows the most ships to be destroyed in total, preferring larger ships
+            let targeted_ship = (0..fleet.ships.len())
+                .filter(|i| can_destroy(fleet, &total_damage, *i))
+                .max_by_key(|i| (kills_after_destroying(hit_graph, fleet, *i), fleet.ships[*i].ship_type.size(), -fleet.hull[*i]));
+
+            if let Some(ship_index) = targeted_ship {
+                hit_graph.destroy(fleet, ship_index);
+            } else {
+                let ship_index = (0..fleet.ships.len())
+                    .filter(|i| fleet.hull[*i] >= 0)
+                    .filter(|i| total_damage[*i] > 0)
+                    .max_by_key(|i| (fleet.ships[*i].ship_type.size(), total_damage[*i]))
+                    .unwrap();
+                hit_graph.damage(fleet, ship_index);
+            }
+        }
+    }
+}
+
+/// Estimates the number of ships destroyed in total if the ship is destroyed first.
+/// The remaining hits destroy the cheapest ships first
+fn kills_after_destroying(hit_graph: &HitGraph, fleet: &Fleet, ship_index: usize) -> usize {
+    let mut remaining_graph = hit_graph.clone();
+    remaining_graph.destroy_ship(ship_index, fleet.hull[ship_index]);
+    let mut hull = fleet.hull.clone();
+    hull[ship_index] = -1;
+    1 + remaining_graph.count_cheapest_first_kills(&mut hull)
+}
+
+/// Destroys as many ships as possible, regardless of their type.
+/// Among the ships that allow the most kills, the one with the highest damage index is destroyed first.
+/// Hits that can not destroy a ship go to the ship with the highest damage index
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MostKills;
+
+impl TargetingStrategy for MostKills {
+    fn assign_hits(&self, hit_graph: &mut HitGraph, fleet: &mut Fleet) {
+        while hit_graph.has_active_edges() {
+            let total_damage = hit_graph.total_possible_damage_per_ship();
+            let targeted_ship = (0..fleet.ships.len())
+                .filter(|i| can_destroy(fleet, &total_damage, *i))
+                .map(|i| (i, kills_after_destroying(hit_graph, fleet, i)))
+                .max_by(|(a, kills_a), (b, kills_b)| kills_a.cmp(kills_b).then_with(|| compare_ship_damage(fleet, *a, *b)))
+                .map(|(i, _)| i);
+
+            match targeted_ship {
+                Some(ship_index) => hit_graph.destroy(fleet, ship_index),
+                None => {
+                    BiggestThreat.assign_hits(hit_graph, fleet);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Destroys or damages the starbases of the fleet first, they can not retreat and usually pin a fleet.
+/// All other hits are assigned like [`BiggestThreat`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FocusStarbase;
+
+impl TargetingStrategy for FocusStarbase {
+    fn assign_hits(&self, hit_graph: &mut HitGraph, fleet: &mut Fleet) {
+        loop {
+            let total_damage = hit_graph.total_possible_damage_per_ship();
+            let starbases = (0..fleet.ships.len())
+                .filter(|i| fleet.ships[*i].ship_type == ShipType::Starbase)
+                .filter(|i| fleet.hull[*i] >= 0 && total_damage[*i] > 0)
+                .collect::<Vec<usize>>();
+            if starbases.is_empty() {
+                break;
+            }
+            match starbases.iter().copied().filter(|i| can_destroy(fleet, &total_damage, *i)).max_by(|a, b| compare_ship_damage(fleet, *a, *b)) {
+                Some(ship_index) => hit_graph.destroy(fleet, ship_index),
+                None => {
+                    let ship_index = starbases.into_iter().max_by(|a, b| compare_ship_damage(fleet, *a, *b)).unwrap();
+                    hit_graph.damage(fleet, ship_index);
+                }
+            }
+        }
+        BiggestThreat.assign_hits(hit_graph, fleet);
+    }
+}
+
+/// Spreads the hits over the fleet instead of focusing them: every hit goes to the ship
+/// with the most remaining hull, starting with the strongest hit.
+/// This destroys as few ships as possible, e.g. to deny the opponent the reputation for a battle
+/// that is already decided
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpreadDamage;
+
+impl TargetingStrategy for SpreadDamage {
+    fn assign_hits(&self, hit_graph: &mut HitGraph, fleet: &mut Fleet) {
+        while let Some((roll, _)) = hit_graph
+            .hits()
+            .map(|edge| (edge.from, edge.damage))
+            .max_by_key(|(roll, damage)| (*damage, std::cmp::Reverse(*roll)))
+        {
+            let ship_index = hit_graph
+                .hits()
+                .filter(|edge| edge.from == roll)
+                .map(|edge| edge.to)
+                .max_by_key(|i| (fleet.hull[*i], std::cmp::Reverse(*i)))
+                .unwrap();
+            hit_graph.assign_hit(fleet, roll, ship_index);
+        }
+    }
+}
+
+/// Searches for the assignment that maximizes the objective.
+/// Exact, but considerably slower than the other strategies
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OptimalAssignment {
+    pub objective: Objective,
+}
+
+impl TargetingStrategy for OptimalAssignment {
+    fn assign_hits(&self, hit_graph: &mut HitGraph, fleet: &mut Fleet) {
+        assign_optimal(hit_graph, fleet, &self.objective);
+    }
+}
+
+/// The value an optimal hit assignment maximizes.
+/// The summed weights of the destroyed ships are maximized first,
+/// ties are broken by the damage dealt to the ships that survive
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Objective {
+    pub interceptor: u32,
+    pub cruiser: u32,
+    pub dreadnought: u32,
+    pub starbase: u32,
+    pub ancient: u32,
+    pub guardian: u32,
+    pub gcds: u32,
+}
+
+impl Default for Objective {
+    /// Player ships are weighted by their material cost.
+    /// NPC ships can not be built, their weights of 3, 5 and 7 rank them from the Ancient to the GCDS
+    fn default() -> Self {
+        let cost = |ship_type: ShipType| ship_type.build_cost().unwrap();
+        Objective {
+            interceptor: cost(ShipType::Interceptor),
+            cruiser: cost(ShipType::Cruiser),
+            dreadnought: cost(ShipType::Dreadnought),
+            starbase: cost(ShipType::Starbase),
+            ancient: 3,
+            guardian: 5,
+            gcds: 7,
+        }
+    }
+}
+
+impl Objective {
+    pub fn weight(&self, ship_type: ShipType) -> u32 {
+        match ship_type {
+            ShipType::Interceptor => self.interceptor,
+            ShipType::Cruiser => self.cruiser,
+            ShipType::Dreadnought => self.dreadnought,
+            ShipType::Starbase => self.starbase,
+            ShipType::Ancient => self.ancient,
+            ShipType::Guardian => self.guardian,
+            ShipType::Gcds => self.gcds,
+        }
+    }
+}
+
+/// The value of destroying a ship always outweighs any damage dealt to the surviving ships
+const DESTROYED_VALUE_SCALE: u64 = 1 << 20;
+
+/// Attack rolls with the same damage that hit the same ships are interchangeable
+#[derive(Debug)]
+struct DiceType {
+    damage: u32,
+    targets: Vec<usize>,
+}
+
+/// Finds the assignment of all hits in the graph that maximizes the objective.
+/// Splittable rolls are split into rolls of one damage each, which allows any split of their damage.
+/// The search runs over the ships, the state is the number of remaining rolls of each dice type.
+/// Since only sets of rolls that do not overshoot the hull of a ship are tried,
+/// the search stays small for typical fleets
+pub(crate) fn assign_optimal(hit_graph: &mut HitGraph, fleet: &mut Fleet, objective: &Objective) {
+    let mut rolls: Vec<(usize, u32, bool, Vec<usize>)> = Vec::new();
+    for edge in hit_graph.edges.iter().filter(|edge| edge.active) {
+        match rolls.iter_mut().find(|(from, ..)| *from == edge.from) {
+            Some((.., targets)) => targets.push(edge.to),
+            None => rolls.push((edge.from, edge.damage, edge.splittable, vec![edge.to])),
+        }
+    }
+    hit_graph.deactivate_all_edges();
+
+    let mut types: Vec<DiceType> = Vec::new();
+    let mut counts: Vec<u16> = Vec::new();
+    // The rolls of each dice type with the number of dice they add, used to record the assignment
+    let mut members: Vec<Vec<(usize, u16)>> = Vec::new();
+    for (roll, damage, splittable, mut targets) in rolls {
+        targets.sort_unstable();
+        let (damage, count) = if splittable { (1, damage as u16) } else { (damage, 1) };
+        match types.iter().position(|t| t.damage == damage && t.targets == targets) {
+            Some(index) => {
+                counts[index] += count;
+                members[index].push((roll, count));
+            }
+            None => {
+                types.push(DiceType { damage, targets });
+                counts.push(count);
+                members.push(vec![(roll, count)]);
+            }
+        }
+    }
+
+    let ships = (0..fleet.ships.len())
+        .filter(|i| fleet.hull[*i] >= 0)
+        .filter(|i| types.iter().any(|t| t.targets.contains(i)))
+        .collect::<Vec<usize>>();
+    let mut search = AssignmentSearch {
+        eligible: ships
+            .iter()
+            .map(|ship| types.iter().map(|t| t.targets.contains(ship)).collect())
+            .collect(),
+        health: ships.iter().map(|i| fleet.hull[*i] as u32 + 1).collect(),
+        weights: ships.iter().map(|i| objective.weight(fleet.ships[*i].ship_type) as u64).collect(),
+        types,
+        memo: HashMap::new(),
+    };
+
+    search.best(0, &counts);
+    // Follow the best choices to apply the damage
+    for (position, ship_index) in ships.iter().enumerate() {
+        let (_, used) = &search.memo[&(position, counts.clone())];
+        let damage: u32 = used
+            .iter()
+            .zip(search.types.iter())
+            .map(|(n, t)| *n as u32 * t.damage)
+            .sum();
+        for ((n, t), rolls) in used.iter().zip(search.types.iter()).zip(members.iter_mut()) {
+            let mut n = *n;
+            while n > 0 {
+                let (roll, count) = rolls.last_mut().unwrap();
+                let dice = n.min(*count);
+                fleet.record_assigned(*roll, *ship_index, dice as u32 * t.damage);
+                n -= dice;
+                *count -= dice;
+                if *count == 0 {
+                    rolls.pop();
+                }
+            }
+        }
+        let hull = fleet.hull[*ship_index];
+        if damage >= search.health[position] {
+            fleet.hull[*ship_index] = -1;
+        } else {
+            fleet.hull[*ship_index] -= damage as i32;
+        }
+        fleet.record_damage(*ship_index, hull - fleet.hull[*ship_index], false);
+        for (count, n) in counts.iter_mut().zip(used.iter()) {
+            *count -= n;
+        }
+    }
+}
+
+struct AssignmentSearch {
+    types: Vec<DiceType>,
+    /// Which dice types can hit each ship
+    eligible: Vec<Vec<bool>>,
+    /// Damage needed to destroy each ship
+    health: Vec<u32>,
+    weights: Vec<u64>,
+    /// The best value for assigning the remaining rolls to the ships starting at a position,
+    /// together with the rolls used for the ship at that position
+    memo: HashMap<(usize, Vec<u16>), (u64, Vec<u16>)>,
+}
+
+impl AssignmentSearch {
+    fn best(&mut self, position: usize, counts: &[u16]) -> u64 {
+        if position == self.health.len() {
+            return 0;
+        }
+        if let Some((value, _)) = self.memo.get(&(position, counts.to_vec())) {
+            return *value;
+        }
+
+        let mut options = Vec::new();
+        let mut used = vec![0; counts.len()];
+        self.collect_options(position, counts, 0, 0, &mut used, &mut options);
+
+        let mut best = (0, vec![0; counts.len()]);
+        for (value, used) in options {
+            let remaining = counts.iter().zip(used.iter()).map(|(c, n)| c - n).collect::<Vec<u16>>();
+            let value = value + self.best(position + 1, &remaining);
+            if value > best.0 {
+                best = (value, used);
+            }
+        }
+        let value = best.0;
+        self.memo.insert((position, counts.to_vec()), best);
+        value
+    }
+
+    /// Collects every set of rolls that can be assigned to the ship at the position
+    /// without using more rolls than needed to destroy it, together with its value
+    fn collect_options(&self, position: usize, counts: &[u16], dice_type: usize, damage: u32, used: &mut Vec<u16>, options: &mut Vec<(u64, Vec<u16>)>) {
+        let health = self.health[position];
+        if damage >= health || dice_type == counts.len() {
+            let value = if damage >= health {
+                self.weights[position] * DESTROYED_VALUE_SCALE
+            } else {
+                damage as u64
+            };
+            options.push((value, used.clone()));
+            return;
+        }
+        if !self.eligible[position][dice_type] {
+            self.collect_options(position, counts, dice_type + 1, damage, used, options);
+            return;
+        }
+        let type_damage = self.types[dice_type].damage;
+        let max_used = (counts[dice_type] as u32).min((health - damage).div_ceil(type_damage));
+        for n in 0..=max_used {
+            used[dice_type] = n as u16;
+            self.collect_options(position, counts, dice_type + 1, damage + n * type_damage, used, options);
+        }
+        used[dice_type] = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::time::{Duration, Instant};
+    use crate::simulator::{simulate_battle_bump, Fleet, HitGraph, Ship, ShipType, Weapon};
+    use crate::targeting::{assign_optimal, BiggestThreat, FocusStarbase, MostKills, Objective, SpreadDamage, Targeting, TargetingStrategy};
+
+    #[test]
+    pub fn test_built_in_strategies() {
+        let bump = bumpalo::Bump::new();
+        let cruiser = Ship::new(1, 0, 0, 0, vec![Weapon::cannon(2)], ShipType::Cruiser);
+        let starbase = Ship::new(1, 0, 0, 0, vec![], ShipType::Starbase);
+        let interceptor = Ship::new(0, 0, 0, 0, vec![], ShipType::Interceptor);
+        let fleet = Fleet::new(vec![cruiser, starbase, interceptor.clone(), interceptor], &bump).unwrap();
+        // Two hits of one damage each, both can hit every ship
+        let hits = |bump| {
+            let mut hit_graph = HitGraph::new(4, bump);
+            for roll in 0..2 {
+                for ship in 0..4 {
+                    hit_graph.add_edge(roll, ship, 1, false);
+                }
+            }
+            hit_graph
+        };
+        let strategies: [(&dyn TargetingStrategy, [i32; 4]); 3] = [
+            (&MostKills, [1, 1, -1, -1]),
+            (&FocusStarbase, [1, -1, 0, 0]),
+            (&SpreadDamage, [0, 0, 0, 0]),
+        ];
+        for (strategy, hull) in strategies {
+            let mut fleet = fleet.clone();
+            let mut hit_graph = hits(&bump);
+            strategy.assign_hits(&mut hit_graph, &mut fleet);
+            assert_eq!(fleet.remaining_hull(), &hull, "{:?}", strategy);
+            assert!(!hit_graph.has_active_edges());
+        }
+    }
+
+    #[test]
+    pub fn test_optimal_assignment_destroys_both_ships() {
+        let bump = bumpalo::Bump::new();
+        let armed = Ship::new(1, 0, 0, 0, vec![Weapon::cannon(2)], ShipType::Cruiser);
+        let shielded = Ship::new(1, 0, 1, 0, vec![], ShipType::Cruiser);
+        let mut fleet = Fleet::new(vec![armed, shielded], &bump).unwrap();
+        // The first roll can only hit the unshielded ship, the second one hits both
+        let mut hit_graph = HitGraph::new(2, &bump);
+        hit_graph.add_edge(0, 0, 2, false);
+        hit_graph.add_edge(1, 0, 2, false);
+        hit_graph.add_edge(1, 1, 2, false);
+        assign_optimal(&mut hit_graph, &mut fleet, &Objective::default());
+        assert!(!fleet.has_ships_left());
+        assert!(!hit_graph.has_active_edges());
+    }
+
+    #[test]
+    pub fn test_optimal_assignment_splits_damage() {
+        let bump = bumpalo::Bump::new();
+        let ship = Ship::new(0, 0, 0, 0, vec![], ShipType::Interceptor);
+        let mut fleet = Fleet::new(vec![ship.clone(), ship.clone(), ship], &bump).unwrap();
+        fleet.hull[2] = 3;
+        let mut hit_graph = HitGraph::new(3, &bump);
+        for target in 0..3 {
+            hit_graph.add_edge(0, target, 4, true);
+        }
+        assign_optimal(&mut hit_graph, &mut fleet, &Objective::default());
+        assert_eq!(fleet.hull.as_slice(), &[-1, -1, 1]);
+    }
+
+    #[test]
+    pub fn test_optimal_assignment_beats_damage_index() {
+        let bump = bumpalo::Bump::new();
+        let ship = |hull: i32| Ship::new(hull, 0, 0, 0, vec![], ShipType::Cruiser);
+        let fleet = Fleet::new(vec![ship(1), ship(0)], &bump).unwrap();
+        // The first roll can only hit the first ship, the second one hits both.
+        // The damage index destroys the ship with more hull first and uses the second roll to do so
+        let hits = || {
+            let mut hit_graph = HitGraph::new(2, &bump);
+            hit_graph.add_edge(0, 0, 2, false);
+            hit_graph.add_edge(1, 0, 2, false);
+            hit_graph.add_edge(1, 1, 2, false);
+            hit_graph
+        };
+
+        let mut greedy = fleet.clone();
+        BiggestThreat.assign_hits(&mut hits(), &mut greedy);
+        assert_eq!(greedy.num_ships(), 1);
+        let mut optimal = fleet.clone();
+        assign_optimal(&mut hits(), &mut optimal, &Objective::default());
+        assert_eq!(optimal.num_ships(), 0);
+    }
+
+    #[test]
+    pub fn test_optimal_battle_with_large_fleets() {
+        let bump = bumpalo::Bump::new();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let interceptor = Ship::new(1, 2, 0, 1, vec![Weapon::cannon(1), Weapon::cannon(2)], ShipType::Interceptor);
+        let cruiser = Ship::new(2, 1, 1, 1, vec![Weapon::cannon(2), Weapon::cannon(4).with_split_damage()], ShipType::Cruiser);
+        let ships = [vec![interceptor; 12], vec![cruiser; 8]].concat();
+        let attacker = Fleet::new(ships.clone(), &bump).unwrap().with_targeting(Targeting::Optimal);
+        let defender = Fleet::new(ships, &bump).unwrap();
+        // The search has to stay fast for about 20 ships per fleet. The bound is generous for unoptimized builds,
+        // the benchmarks measure the actual speed
+        let start = Instant::now();
+        for _ in 0..20 {
+            simulate_battle_bump(&mut attacker.clone(), &mut defender.clone(), &mut rng, &bump);
+        }
+        assert!(start.elapsed() < Duration::from_secs(10), "{:?}", start.elapsed());
+    }
+}
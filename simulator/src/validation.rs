@@ -0,0 +1,80 @@
+use crate::error::{FleetError, StatViolation};
+use crate::simulator::{Ship, WeaponKind};
+
+/// The damage of the strongest weapon part, the antimatter cannon
+const MAX_WEAPON_DAMAGE: i32 = 4;
+
+impl Ship {
+    /// All stats of the ship that are out of range: negative stats, weapons without damage or dice
+    /// and weapon damage above that of an antimatter cannon. The violations refer to ship 0
+    pub fn stat_violations(&self) -> Vec<StatViolation> {
+        let mut violations = Vec::new();
+        let mut check = |field: String, value: i32, min: i32, max: i32| {
+            if value < min || value > max {
+                violations.push(StatViolation { ship: 0, field, value, min, max });
+            }
+        };
+        check("hull".to_string(), self.hull, 0, i32::MAX);
+        check("initiative".to_string(), self.initiative, 0, i32::MAX);
+        check("shield".to_string(), self.shield, 0, i32::MAX);
+        check("computer".to_string(), self.computer, 0, i32::MAX);
+        for (index, weapon) in self.weapons.iter().enumerate() {
+            // The damage of rift dice is given by the die faces
+            if weapon.kind != WeaponKind::Rift {
+                check(format!("weapons[{}].damage", index), weapon.damage, 1, MAX_WEAPON_DAMAGE);
+            }
+            check(format!("weapons[{}].dice", index), weapon.dice, 1, i32::MAX);
+        }
+        violations
+    }
+}
+
+/// Checks the stats of all ships of a fleet and reports every violation
+pub fn validate_ships(ships: &[Ship]) -> Result<(), FleetError> {
+    let violations = ships
+        .iter()
+        .enumerate()
+        .flat_map(|(index, ship)| {
+            ship.stat_violations().into_iter().map(move |violation| StatViolation { ship: index, ..violation })
+        })
+        .collect::<Vec<StatViolation>>();
+    match violations.is_empty() {
+        true => Ok(()),
+        false => Err(FleetError::InvalidStats(violations)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bumpalo::Bump;
+    use crate::error::{FleetError, StatViolation};
+    use crate::simulator::{Fleet, Ship, ShipType, WasmFleet, Weapon};
+    use crate::validation::validate_ships;
+
+    #[test]
+    pub fn test_validate_ships() {
+        let cruiser = Ship::new(1, 2, 0, 1, vec![Weapon::cannon(2), Weapon::rift_cannon()], ShipType::Cruiser);
+        assert!(validate_ships(&[cruiser.clone(), Ship::gcds_advanced()]).is_ok());
+
+        let broken = Ship::new(-1, 2, -2, 1, vec![Weapon::cannon(1), Weapon::cannon(5), Weapon::missile(0)], ShipType::Interceptor);
+        let error = validate_ships(&[cruiser.clone(), broken.clone()]).unwrap_err();
+        let FleetError::InvalidStats(violations) = &error else { panic!("{}", error) };
+        let fields = violations.iter().map(|violation| violation.field.as_str()).collect::<Vec<&str>>();
+        assert_eq!(fields, ["hull", "shield", "weapons[1].damage", "weapons[2].damage"]);
+        assert!(violations.iter().all(|violation| violation.ship == 1));
+        assert_eq!(violations[2], StatViolation { ship: 1, field: "weapons[1].damage".to_string(), value: 5, min: 1, max: 4 });
+        assert_eq!(
+            error.to_string(),
+            "invalid fleet: ships[1].hull is -1 but must be at least 0, ships[1].shield is -2 but must be at least 0, \
+             ships[1].weapons[1].damage is 5 but must be between 1 and 4, ships[1].weapons[2].damage is 0 but must be between 1 and 4"
+        );
+
+        let bump = Bump::new();
+        assert!(Fleet::new(vec![cruiser.clone(), broken.clone()], &bump).is_err());
+        assert!(WasmFleet::new(vec![broken]).is_err());
+
+        let json = WasmFleet::new(vec![cruiser]).unwrap().to_json().unwrap();
+        let error = WasmFleet::from_json(&json.replacen("\"initiative\":2", "\"initiative\":-1", 1)).unwrap_err();
+        assert!(matches!(&error, FleetError::InvalidStats(violations) if violations[0].field == "initiative"), "{}", error);
+    }
+}
@@ -1,18 +1,37 @@
-import {BattleResult, WasmFleet, RngState, simulate_battle, simulate_n_battles} from "simulator";
+import {BattleResult, WasmFleet, RngState, simulate_battle, simulate_until_precision} from "simulator";
 
 self.onmessage = (e: MessageEvent) => {
     let rng_state = new RngState(BigInt(e.data.rng_seed));
-    const attacker_fleet = WasmFleet.from_json(e.data.attacker_fleet);
-    const defender_fleet = WasmFleet.from_json(e.data.defender_fleet);
+    let attacker_fleet: WasmFleet;
+    let defender_fleet: WasmFleet;
+    try {
+        attacker_fleet = WasmFleet.from_json(e.data.attacker_fleet);
+        defender_fleet = WasmFleet.from_json(e.data.defender_fleet);
+    } catch (error) {
+        // The error message names the field that could not be read
+        self.postMessage({error: String(error)});
+        return;
+    }
     console.log("Received attacker fleet: ", attacker_fleet.to_json());
     console.log("Received defender fleet: ", defender_fleet.to_json());
     const n: number = e.data.simulation_steps;
 
 
-    let win_percent = simulate_n_battles(attacker_fleet, defender_fleet, n, rng_state);
-    console.log("Win percent: ", win_percent);
+    // Stop once the defender win rate is known to within half a percentage point,
+    // the whole confidence interval is then narrower than one percentage point
+    const statistics = simulate_until_precision(attacker_fleet, defender_fleet, 0.01, n, rng_state);
+    const interval = statistics.defender_win_interval();
+    console.log("Defender win percent: ", statistics.defender_win_rate());
 
-    self.postMessage({defender_win_percent: win_percent});
+    self.postMessage({
+        defender_win_percent: statistics.defender_win_rate(),
+        attacker_win_percent: statistics.attacker_win_rate(),
+        draw_percent: statistics.draw_rate(),
+        defender_win_low: interval.low,
+        defender_win_high: interval.high,
+        rounds: statistics.rounds,
+        battles: statistics.battles,
+    });
 }
 
 export {};
\ No newline at end of file